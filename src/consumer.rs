@@ -56,14 +56,17 @@
 //!  // It can consume data directly from a producer
 //!  let mut p = MemProducer::new(b"abcdefghijklmnopqrstuvwx", 4);
 //!  let mut c = TestPrintConsumer::new();
-//!  c.run(&mut p);
+//!  c.run(&mut p).unwrap();
 //! ```
 
 use self::ConsumerState::*;
 use producer::Producer;
 use producer::ProducerState::*;
 use internal::Err;
+use std::io;
 use std::io::SeekFrom;
+use std::error;
+use std::fmt::{self,Display,Formatter};
 use util::HexDisplay;
 
 /// Holds the current state of the consumer
@@ -73,7 +76,7 @@ use util::HexDisplay;
 /// * ConsumerDone if the consumer does not need anymore data to be parsed
 ///
 /// * ConsumerError when something went wrong
-#[derive(Debug,PartialEq,Eq,Copy)]
+#[derive(Debug,PartialEq,Eq,Clone)]
 pub enum ConsumerState {
   Await(
     usize,    // consumed
@@ -89,14 +92,54 @@ pub enum ConsumerState {
   ConsumerError(Err)
 }
 
+/// error returned by `Consumer::run` when it stops before the end of the data
+///
+/// * Producer holds the error of the producer
+///
+/// * Io holds the error returned by the producer's reader
+///
+/// * Seek holds the position asked by the consumer, if the producer could not seek to it
+///
+/// * Consumer holds the error of the consumer
+#[derive(Debug)]
+pub enum RunError {
+  Producer(Err),
+  Io(io::Error),
+  Seek(SeekFrom),
+  Consumer(Err)
+}
+
+impl Display for RunError {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      &RunError::Producer(ref e) => write!(f, "producer error: {}", e),
+      &RunError::Io(ref e)       => write!(f, "I/O error: {}", e),
+      &RunError::Seek(ref p)     => write!(f, "the producer could not seek to {:?}", p),
+      &RunError::Consumer(ref e) => write!(f, "consumer error: {}", e)
+    }
+  }
+}
+
+impl error::Error for RunError {
+  fn description(&self) -> &str {
+    match self {
+      &RunError::Producer(_) => "producer error",
+      &RunError::Io(ref e)   => e.description(),
+      &RunError::Seek(_)     => "seek error",
+      &RunError::Consumer(_) => "consumer error"
+    }
+  }
+}
+
 /// Implement the consume method, taking a byte array as input and returning a consumer state
 ///
-/// The run function takes care of continuing or not
+/// The run function takes care of continuing or not. It returns an error if the producer
+/// or the consumer fails, and does not call `end` in that case
 pub trait Consumer {
   fn consume(&mut self, input: &[u8]) -> ConsumerState;
   fn end(&mut self);
 
-  fn run(&mut self, producer: &mut Producer) {
+  fn run(&mut self, producer: &mut Producer) -> Result<(), RunError> {
     let mut acc: Vec<u8>      = Vec::new();
     let mut position          = 0;
     let mut shouldSeek        = false;
@@ -118,8 +161,9 @@ pub trait Consumer {
         acc = tmp;
       } else {
         if shouldSeek {
-          let pos = producer.seek(seekFrom);
-          //println!("seeking: {:?}", pos);
+          if producer.seek(seekFrom).is_none() {
+            return Err(RunError::Seek(seekFrom))
+          }
           shouldSeek = false;
           acc.clear();
         } else {
@@ -141,7 +185,7 @@ pub trait Consumer {
               //println!("eof empty");
               eof = true;
               self.end();
-              return
+              return Ok(())
             }
             Eof(v) => {
               //println!("eof with {} bytes", v.len());
@@ -150,8 +194,15 @@ pub trait Consumer {
               position = position + v.len();
               break;
             }
-            ProducerError(e) => {break;}
-            Continue => { continue; }
+            ProducerError(e) => return Err(RunError::Producer(e)),
+            IoError(e)       => return Err(RunError::Io(e)),
+            Continue => {
+              // blocks until the producer has data, instead of spinning
+              if let Err(e) = producer.wait() {
+                return Err(RunError::Io(e))
+              }
+              continue;
+            }
           }
           //println!("acc size: {}", acc.len());
           if acc.len() >= needed { break; }
@@ -163,6 +214,7 @@ pub trait Consumer {
       match self.consume(&acc[0..needed]) {
         ConsumerError(e) => {
           //println!("consumer error, stopping: {}", e);
+          return Err(RunError::Consumer(e))
         },
         ConsumerDone => {
           //println!("data, done");
@@ -196,6 +248,7 @@ pub trait Consumer {
         break;
       }
     }
+    Ok(())
   }
 }
 
@@ -203,10 +256,11 @@ pub trait Consumer {
 mod tests {
   use super::*;
   use super::ConsumerState::*;
-  use producer::MemProducer;
+  use producer::{MemProducer,ReadProducer};
   use internal::{Needed,IResult};
+  use internal::Err::*;
   use std::str;
-  use std::io::SeekFrom;
+  use std::io::{self,Read,SeekFrom,ErrorKind};
 
 #[macro_export]
 macro_rules! take(
@@ -261,8 +315,106 @@ macro_rules! take(
   fn pull() {
     let mut p = MemProducer::new(b"abcdefghijklmnopqrstuvwx", 4);
     let mut c = TestPrintConsumer::new();
-    c.run(&mut p);
+    assert!(c.run(&mut p).is_ok());
 
     assert!(c.ended);
   }
+  #[cfg(unix)]
+  #[test]
+  fn poll() {
+    use producer::FdProducer;
+    use test_util::stream_pair;
+    use std::io::Write;
+    use std::thread;
+
+    let (a, mut b, reads) = stream_pair();
+    let mut p = FdProducer::new(a, 4).unwrap();
+    // the data arrives in pieces, the consumer waits for each of them: a piece is
+    // written once the consumer has read the previous one
+    let writer = thread::spawn(move || {
+      let mut read = 0;
+      let mut sent = 0;
+      for chunk in [&b"abcdef"[..], &b"ghijklmn"[..], &b"opqrst"[..]].iter() {
+        while read < sent {
+          read += reads.recv().unwrap();
+        }
+        b.write_all(chunk).unwrap();
+        sent += chunk.len();
+      }
+    });
+
+    let mut c = TestPrintConsumer::new();
+    assert!(c.run(&mut p).is_ok());
+    assert!(c.ended);
+    writer.join().unwrap();
+  }
+
+  // asks for data, then fails
+  struct FailingConsumer;
+
+  impl Consumer for FailingConsumer {
+    fn consume(&mut self, input: &[u8]) -> ConsumerState {
+      if input.len() == 0 {
+        Await(0, 4)
+      } else {
+        ConsumerError(Code(1))
+      }
+    }
+
+    fn end(&mut self) {
+      panic!("end should not be called after an error");
+    }
+  }
+
+  struct BrokenReader;
+
+  impl Read for BrokenReader {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+      Err(io::Error::new(ErrorKind::BrokenPipe, "broken"))
+    }
+  }
+
+  #[test]
+  fn errors() {
+    let mut p = MemProducer::new(b"abcdefgh", 4);
+    match FailingConsumer.run(&mut p) {
+      Err(RunError::Consumer(Code(1))) => {},
+      r                                => panic!("expected a consumer error, got {:?}", r)
+    }
+
+    let mut p = ReadProducer::new(BrokenReader, 4);
+    match FailingConsumer.run(&mut p) {
+      Err(RunError::Io(ref e)) if e.kind() == ErrorKind::BrokenPipe => {},
+      r                                                           => panic!("expected an I/O error, got {:?}", r)
+    }
+  }
+
+  // skips the first 4 bytes
+  struct SeekingConsumer;
+
+  impl Consumer for SeekingConsumer {
+    fn consume(&mut self, input: &[u8]) -> ConsumerState {
+      if input.len() == 0 {
+        Await(0, 4)
+      } else {
+        Seek(0, SeekFrom::Start(4), 4)
+      }
+    }
+
+    fn end(&mut self) {
+      panic!("end should not be called after an error");
+    }
+  }
+
+  #[test]
+  fn seek_error() {
+    // a ReadProducer cannot seek
+    let mut p = ReadProducer::new(&b"abcdefgh"[..], 4);
+    match SeekingConsumer.run(&mut p) {
+      Err(RunError::Seek(SeekFrom::Start(4))) => {},
+      r                                       => panic!("expected a seek error, got {:?}", r)
+    }
+  }
+
+
 }
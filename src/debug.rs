@@ -0,0 +1,199 @@
+//! Debugging helpers
+//!
+//! `dbg_dmp!` wraps a parser, and when it fails or needs more data, writes the name
+//! of the parser, its result and a hex dump of its input.
+//!
+//! With the `trace` feature, the parsers generated by the macros with a name
+//! (like `tag!(name ...)` or `chain!(name<I,O>, ...)`) log their entry and exit,
+//! with the offset in the input and an indentation showing the nesting depth:
+//!
+//! ```ignore
+//!  -> key_value at offset 0
+//!    -> equal at offset 4
+//!    <- equal Done at offset 5
+//!  <- key_value Done at offset 12
+//! ```
+//!
+//! Without that feature, the tracing functions are empty and compile to nothing.
+//!
+//! The messages are written to a sink, stderr by default, that can be replaced
+//! for the current thread with `set_debug_sink`
+
+use std::cell::RefCell;
+use std::io::{self,Write};
+use std::mem;
+use internal::IResult;
+use internal::IResult::*;
+use util::{AsBytes,HexDisplay};
+
+#[cfg(feature = "trace")]
+use std::cell::Cell;
+
+thread_local!(static SINK: RefCell<Box<Write>> = RefCell::new(Box::new(io::stderr())));
+
+#[cfg(feature = "trace")]
+thread_local!(static DEPTH: Cell<usize> = Cell::new(0));
+#[cfg(feature = "trace")]
+thread_local!(static BASE: Cell<usize> = Cell::new(0));
+
+/// replaces the sink of the debug messages of the current thread, and returns the previous one
+pub fn set_debug_sink(sink: Box<Write>) -> Box<Write> {
+  SINK.with(|s| mem::replace(&mut *s.borrow_mut(), sink))
+}
+
+/// writes a message to the debug sink of the current thread
+pub fn debug_write(message: &str) {
+  SINK.with(|s| {
+    let mut sink = s.borrow_mut();
+    let _ = sink.write_all(message.as_bytes());
+    let _ = sink.flush();
+  })
+}
+
+/// writes the name of a parser, its result and a hex dump of its input, if it did not succeed
+///
+/// used by `dbg_dmp!`
+pub fn dump<O>(name: &str, input: &[u8], res: &IResult<&[u8],O>) {
+  let result = match *res {
+    Done(_,_)         => return,
+    Error(ref e)      => format!("Error({:?})", e),
+    Incomplete(ref n) => format!("Incomplete({:?})", n)
+  };
+  debug_write(&format!("{}: {} on input:\n{}", name, result, input.to_hex(8)));
+}
+
+/// logs the entry of a named parser, used by `trace_parser!`
+///
+/// the first parser called sets the beginning of the input, from which the offsets are counted
+#[cfg(feature = "trace")]
+pub fn trace_enter<I:AsBytes>(name: &str, input: &I) {
+  let position = input.as_bytes().as_ptr() as usize;
+  let depth    = DEPTH.with(|d| { let depth = d.get(); d.set(depth + 1); depth });
+  if depth == 0 {
+    BASE.with(|b| b.set(position));
+  }
+  let offset   = BASE.with(|b| position.wrapping_sub(b.get()));
+  debug_write(&format!("{}-> {} at offset {}\n", indent(depth), name, offset));
+}
+
+/// logs the exit of a named parser and its result, used by `trace_parser!`
+#[cfg(feature = "trace")]
+pub fn trace_exit<I:AsBytes,O>(name: &str, res: &IResult<I,O>) {
+  let depth = DEPTH.with(|d| { let depth = d.get() - 1; d.set(depth); depth });
+  let result = match *res {
+    Done(ref i,_)     => {
+      let position = i.as_bytes().as_ptr() as usize;
+      format!("Done at offset {}", BASE.with(|b| position.wrapping_sub(b.get())))
+    },
+    Error(ref e)      => format!("Error({:?})", e),
+    Incomplete(ref n) => format!("Incomplete({:?})", n)
+  };
+  debug_write(&format!("{}<- {} {}\n", indent(depth), name, result));
+}
+
+#[cfg(feature = "trace")]
+fn indent(depth: usize) -> String {
+  let mut s = String::with_capacity(depth * 2);
+  for _ in 0..depth {
+    s.push_str("  ");
+  }
+  s
+}
+
+#[cfg(not(feature = "trace"))]
+#[inline(always)]
+pub fn trace_enter<I:AsBytes>(_: &str, _: &I) {}
+
+#[cfg(not(feature = "trace"))]
+#[inline(always)]
+pub fn trace_exit<I:AsBytes,O>(_: &str, _: &IResult<I,O>) {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use internal::{Err,IResult,Needed};
+  use internal::IResult::*;
+  use std::cell::RefCell;
+  use std::io::{self,Write};
+  use std::rc::Rc;
+  use std::str;
+
+  // a sink the test can read after the parsers wrote to it
+  struct Shared(Rc<RefCell<Vec<u8>>>);
+
+  impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  fn capture<F:Fn()>(f: F) -> String {
+    let buffer = Rc::new(RefCell::new(Vec::new()));
+    let previous = set_debug_sink(Box::new(Shared(buffer.clone())));
+    f();
+    set_debug_sink(previous);
+    let v = buffer.borrow().clone();
+    String::from_utf8(v).unwrap()
+  }
+
+  #[test]
+  fn dbg_dmp() {
+    fn x(input: &[u8]) -> IResult<&[u8], &[u8]> {
+      if input.starts_with(b"abcd") { Done(&input[4..], &input[..4]) } else { Error(Err::Code(0)) }
+    }
+    dbg_dmp!(dx<&[u8], &[u8]>, x);
+
+    // with the trace feature, the named form also writes its entry and exit
+    let out = capture(|| { assert_eq!(dx(&b"abcdef"[..]), Done(&b"ef"[..], &b"abcd"[..])); });
+    assert!(!out.contains("on input"));
+
+    let out = capture(|| { assert!(dx(&b"efgh"[..]).is_err()); });
+    assert!(out.contains("dx: Error(Code(0)) on input:\n"));
+    assert!(out.contains("65 66 67 68"));
+
+    let out = capture(|| {
+      let r: IResult<&[u8], &[u8]> = dbg_dmp!(&b"ab"[..], take!(4));
+      assert_eq!(r, Incomplete(Needed::Size(4)));
+    });
+    assert!(out.contains("Incomplete(Size(4)) on input:\n"));
+  }
+
+  #[cfg(feature = "trace")]
+  #[test]
+  fn trace() {
+    tag!(x "ab");
+    tag!(y "cd");
+    pair!(xy<&[u8], (&[u8], &[u8])>, x, y);
+    many_m_n!(multi<&[u8], (&[u8], &[u8])>, 1, 2, xy);
+
+    let out = capture(|| { multi(&b"abcdab;;"[..]); });
+    assert_eq!(out, "-> multi at offset 0
+  -> xy at offset 0
+    -> x at offset 0
+    <- x Done at offset 2
+    -> y at offset 2
+    <- y Done at offset 4
+  <- xy Done at offset 4
+  -> xy at offset 4
+    -> x at offset 4
+    <- x Done at offset 6
+    -> y at offset 6
+    <- y Error(Code(0))
+  <- xy Error(Code(0))
+<- multi Done at offset 4
+");
+
+    fold0!(count<&[u8], usize>, |acc, _| { acc + 1 }, x);
+    let out = capture(|| { count(&b"ab;;"[..], 0); });
+    assert_eq!(out, "-> count at offset 0
+  -> x at offset 0
+  <- x Done at offset 2
+  -> x at offset 2
+  <- x Error(Code(0))
+<- count Done at offset 2
+");
+  }
+}
@@ -2,9 +2,149 @@
 
 use self::IResult::*;
 use std::fmt::{Debug,Display,Formatter,Result};
+use std::error;
+use std::result;
+use std::sync::Arc;
+use util::Offset;
 
-/// Errortype. A integer for now
-pub type Err = u32;
+/// Errortype
+///
+/// * Code is an error code, for a parser that failed. `Err` used to be a `u32` code,
+/// `From<u32>` converts those codes: `Error(0)` becomes `Error(Code(0))` or `Error(0.into())`
+///
+/// * Alt holds the errors of all the branches of an `alt!` that failed, in order
+///
+/// * Conversion holds the error returned by the function of a `map_res!`
+///
+/// * Missing holds the position of a required member of a `permutation!` that was not found
+///
+/// * Duplicate holds the position of a member of a `permutation!` that appeared twice
+///
+/// * ForeignInput indicates that a sub-parser returned a remaining input that is not a part
+/// of its input, like a `b""` literal instead of `&input[input.len()..]`
+///
+/// * Position holds an error and the offset in the input where it happened
+///
+/// * Verify indicates that the output of the parser of a `verify!` did not satisfy the predicate
+///
+/// * UnexpectedEnd is returned by `complete!` when a parser needed more data than the complete
+/// input holds
+///
+/// * Switch indicates that no arm of a `switch!` matched the output of its selector
+///
+/// * LeftRecursion is the answer of the recursive call of a left recursive parser memoized with
+/// `Memo::apply_left_rec`, before that parser succeeded at that position
+#[derive(Debug,PartialEq,Eq,Clone)]
+pub enum Err {
+  Code(u32),
+  Alt(Vec<Err>),
+  Conversion(ConversionError),
+  Missing(usize),
+  Duplicate(usize),
+  ForeignInput,
+  Position(usize, Box<Err>),
+  Verify,
+  UnexpectedEnd(Needed),
+  Switch,
+  LeftRecursion
+}
+
+impl Err {
+  /// locates the error at the beginning of `remaining`, a part of `input`
+  ///
+  /// ```
+  /// use nom::Err;
+  /// let input = &b"abcdefgh"[..];
+  /// assert_eq!(Err::Code(1).at(input, &input[3..]), Err::Position(3, Box::new(Err::Code(1))));
+  /// ```
+  pub fn at<T:Offset+?Sized>(self, input: &T, remaining: &T) -> Err {
+    match self {
+      Err::Position(p, e) => Err::Position(input.offset(remaining) + p, e),
+      e                   => Err::Position(input.offset(remaining), Box::new(e))
+    }
+  }
+
+  /// makes the position of a located error relative to `input`, if it was relative
+  /// to `remaining`, a part of `input`
+  ///
+  /// the combinators applying parsers in sequence use it, so the position of an error
+  /// located with `locate!` or `at` counts from the beginning of the outermost input.
+  /// Errors without a position are not changed
+  pub fn relocate<T:Offset+?Sized>(self, input: &T, remaining: &T) -> Err {
+    match self {
+      Err::Position(p, e) => Err::Position(input.offset(remaining) + p, e),
+      e                   => e
+    }
+  }
+
+  /// offset of the error in the input, if it was located
+  pub fn position(&self) -> Option<usize> {
+    match self {
+      &Err::Position(p, _) => Some(p),
+      _                    => None
+    }
+  }
+}
+
+/// error returned by the function of a `map_res!`
+///
+/// the error is shared, so that `Err` can still be cloned. Errors cannot be compared,
+/// so two conversion errors are equal if they have the same description and message
+#[derive(Clone)]
+pub struct ConversionError(Arc<Box<error::Error+Send+Sync>>);
+
+impl ConversionError {
+  pub fn new<E:error::Error+Send+Sync+'static>(e: E) -> ConversionError {
+    ConversionError(Arc::new(Box::new(e)))
+  }
+
+  /// the error returned by the conversion function
+  pub fn error(&self) -> &(error::Error+Send+Sync) {
+    &**self.0
+  }
+
+  /// conversion error of a function whose error type does not implement
+  /// `std::error::Error`, so the error itself cannot be kept
+  pub fn unknown() -> ConversionError {
+    ConversionError::new(UnknownConversionError)
+  }
+}
+
+#[derive(Debug)]
+struct UnknownConversionError;
+
+impl Display for UnknownConversionError {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    write!(f, "the conversion function failed")
+  }
+}
+
+impl error::Error for UnknownConversionError {
+  fn description(&self) -> &str {
+    "conversion error"
+  }
+}
+
+impl Debug for ConversionError {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    Debug::fmt(self.error(), f)
+  }
+}
+
+impl Display for ConversionError {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    Display::fmt(self.error(), f)
+  }
+}
+
+impl PartialEq for ConversionError {
+  fn eq(&self, other: &ConversionError) -> bool {
+    self.error().description() == other.error().description() &&
+      self.error().to_string() == other.error().to_string()
+  }
+}
+
+impl Eq for ConversionError {}
 
 /// (Experimental) Closure used to hold the temporary state of resumable parsing
 pub type IResultClosure<'a,I,O> = Box<FnMut(I) -> IResult<I,O> +'a>;
@@ -33,12 +173,81 @@ impl<'a,I:Eq,O:Eq> Eq for IResultClosure<'a,I,O> {}
 //type IResultClosure<'a,I,O> = |I|:'a -> IResult<'a,I,O>;
 //type IResultClosure<'a,I,O> = Fn<I, IResult<'a,I,O>>;
 
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
 pub enum Needed {
   Unknown,
   Size(u32)
 }
 
+/// converts the error codes of the versions where `Err` was a `u32`
+///
+/// ```
+/// use nom::{Err,IResult};
+/// let r: IResult<&[u8], u8> = IResult::Error(1.into());
+/// assert_eq!(r, IResult::Error(Err::Code(1)));
+/// ```
+impl From<u32> for Err {
+  fn from(code: u32) -> Err {
+    Err::Code(code)
+  }
+}
+
+impl Display for Err {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    match self {
+      &Err::Code(ref c)        => write!(f, "error code {}", c),
+      &Err::Alt(ref errors)    => write!(f, "no alternative matched: {:?}", errors),
+      &Err::Conversion(ref e)  => write!(f, "conversion error: {}", e),
+      &Err::Missing(ref p)     => write!(f, "missing member {} of the permutation", p),
+      &Err::Duplicate(ref p)   => write!(f, "duplicated member {} of the permutation", p),
+      &Err::ForeignInput       => write!(f, "the remaining input is not a part of the input"),
+      &Err::Position(p, ref e) => write!(f, "{} at offset {}", e, p),
+      &Err::Verify             => write!(f, "the output does not satisfy the predicate"),
+      &Err::UnexpectedEnd(n)   => write!(f, "unexpected end of the input, {} were needed", n),
+      &Err::Switch             => write!(f, "no arm of the switch matched"),
+      &Err::LeftRecursion      => write!(f, "the left recursive parser did not match yet")
+    }
+  }
+}
+
+impl Display for Needed {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    match self {
+      &Needed::Unknown => write!(f, "an unknown number of bytes"),
+      &Needed::Size(s) => write!(f, "{} bytes", s)
+    }
+  }
+}
+
+/// error type used to convert an IResult to a std Result
+///
+/// * Error holds the error of a parser that failed
+///
+/// * Incomplete indicates that the parser needed more data
+#[derive(Debug,PartialEq,Eq,Clone)]
+pub enum ParseError {
+  Error(Err),
+  Incomplete(Needed)
+}
+
+impl Display for ParseError {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    match self {
+      &ParseError::Error(ref e)      => write!(f, "parsing error: {}", e),
+      &ParseError::Incomplete(ref n) => write!(f, "incomplete input, the parser needs {}", n)
+    }
+  }
+}
+
+impl error::Error for ParseError {
+  fn description(&self) -> &str {
+    match self {
+      &ParseError::Error(_)      => "parsing error",
+      &ParseError::Incomplete(_) => "incomplete input"
+    }
+  }
+}
+
 /// Holds the result of parsing functions
 ///
 /// It depends on I, the input types, and O, the output type.
@@ -46,12 +255,11 @@ pub enum Needed {
 /// * Done indicates a correct parsing, the first field containing the rest of the unparsed data,
 /// the second field contains the parsed data
 ///
-/// * Error is currently a u32, but should be updated to indicate which parser had a problem,
-/// a description, and an eventual stack of parser to know which path failed
+/// * Error holds an Err, currently an error code, or the list of errors of the branches of an `alt!`
 ///
 /// * Incomplete will hold the closure used to restart the computation once more data is available.
 /// Current attemps at implementation of Incomplete are progressing, but slowed down by lifetime problems
-#[derive(Debug,PartialEq,Eq)]
+#[derive(Debug,PartialEq,Eq,Clone)]
 pub enum IResult<I,O> {
   Done(I,O),
   Error(Err),
@@ -64,6 +272,40 @@ pub enum IResult<I,O> {
 }
 
 impl<I,O> IResult<I,O> {
+  /// converts to a std Result. `Incomplete` becomes `Err(ParseError::Incomplete(needed))`
+  pub fn to_result(self) -> result::Result<(I,O), ParseError> {
+    match self {
+      Done(i, o)    => Ok((i, o)),
+      Error(e)      => Err(ParseError::Error(e)),
+      Incomplete(n) => Err(ParseError::Incomplete(n))
+    }
+  }
+
+  /// converts back from the std Result returned by `to_result`
+  pub fn from_result(res: result::Result<(I,O), ParseError>) -> IResult<I,O> {
+    match res {
+      Ok((i, o))                    => Done(i, o),
+      Err(ParseError::Error(e))      => Error(e),
+      Err(ParseError::Incomplete(n)) => Incomplete(n)
+    }
+  }
+
+  /// returns the output of a parser applied to complete input
+  ///
+  /// since no more data will come, `Incomplete` is returned as an error,
+  /// and the remaining input is dropped
+  ///
+  /// ```
+  /// use nom::IResult::{Done,Incomplete};
+  /// use nom::{IResult,Needed,ParseError};
+  /// let res: IResult<&[u8], u8> = Incomplete(Needed::Size(2));
+  /// assert_eq!(Done(&b""[..], 1u8).finish(), Ok(1));
+  /// assert_eq!(res.finish(), Err(ParseError::Incomplete(Needed::Size(2))));
+  /// ```
+  pub fn finish(self) -> result::Result<O, ParseError> {
+    self.to_result().map(|(_, o)| o)
+  }
+
   pub fn is_done(&self) -> bool {
     match self {
       &Done(_,_) => true,
@@ -130,3 +372,68 @@ impl<'a,I> GetOutput<()> for IResult<I,()> {
   }
 }
 
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::IResult::*;
+  use super::Err::*;
+  use std::error::Error as StdError;
+  use std::result;
+
+  fn parse_twice(input: &[u8]) -> result::Result<(u8, u8), ParseError> {
+    let (i, a) = try!(be_u8(input).to_result());
+    let b      = try!(be_u8(i).finish());
+    Ok((a, b))
+  }
+
+  fn be_u8(i: &[u8]) -> IResult<&[u8], u8> {
+    if i.len() < 1 {
+      Incomplete(Needed::Size(1))
+    } else {
+      Done(&i[1..], i[0])
+    }
+  }
+
+  #[test]
+  fn to_result() {
+    let a: IResult<&[u8], u8> = Done(&b"ab"[..], 1);
+    let b: IResult<&[u8], u8> = Error(Code(1));
+    let c: IResult<&[u8], u8> = Incomplete(Needed::Size(2));
+
+    assert_eq!(a.clone().to_result(), Ok((&b"ab"[..], 1)));
+    assert_eq!(b.clone().to_result(), Err(ParseError::Error(Code(1))));
+    assert_eq!(c.clone().to_result(), Err(ParseError::Incomplete(Needed::Size(2))));
+
+    assert_eq!(IResult::from_result(a.clone().to_result()), a);
+    assert_eq!(IResult::from_result(b.clone().to_result()), b);
+    assert_eq!(IResult::from_result(c.clone().to_result()), c);
+  }
+
+  #[test]
+  fn finish() {
+    assert_eq!(parse_twice(b"\x01\x02"), Ok((1, 2)));
+    assert_eq!(parse_twice(b"\x01"), Err(ParseError::Incomplete(Needed::Size(1))));
+
+    let e = parse_twice(b"").unwrap_err();
+    assert_eq!(e.description(), "incomplete input");
+    assert_eq!(format!("{}", e), "incomplete input, the parser needs 1 bytes");
+    assert_eq!(format!("{}", ParseError::Error(Alt(vec![Code(1)]))), "parsing error: no alternative matched: [Code(1)]");
+  }
+
+  #[test]
+  fn position() {
+    let input = &b"abcdefgh"[..];
+    let e = Code(1).at(&input[2..], &input[5..]);
+    assert_eq!(e, Position(3, Box::new(Code(1))));
+    assert_eq!(e.clone().relocate(input, &input[2..]).position(), Some(5));
+    assert_eq!(Code(1).relocate(input, &input[2..]), Code(1));
+    assert_eq!(format!("{}", e), "error code 1 at offset 3");
+  }
+
+  #[test]
+  fn from_code() {
+    let e: Err = From::from(2);
+    assert_eq!(e, Code(2));
+  }
+}
@@ -18,24 +18,30 @@
 //!    let mut p = producer;
 //!
 //!    // create the parsing function
-//!    fn parser(par: IResult<(),&[u8]>) -> IResult<&[u8],()> {
+//!    fn parser(input: &[u8]) -> IResult<&[u8],()> {
 //!      // convert byte array to a string, then print it
-//!      par.map_res(str::from_utf8).flat_map(local_print);
+//!      Done(input, input).map_res(str::from_utf8).flat_map(local_print);
 //!
-//!      // return a dummy answer
-//!      Done(b"", ())
+//!      // everything was consumed: the remaining input is the end of the input.
+//!      // It must be a part of the input, not a b"" literal
+//!      Done(&input[input.len()..], ())
 //!    }
 //!
 //!    // adapt the parsing function to the producer
 //!    pusher!(push, parser);
 //!    // get started
-//!    push(&mut p);
+//!    push(&mut p).unwrap();
 //!  });
 //! ```
 //!
 
 #![feature(fs,path,io,core,collections)]
 
+#[cfg(feature = "profile")]
+extern crate time;
+#[cfg(unix)]
+extern crate libc;
+
 pub use self::util::*;
 pub use self::internal::*;//{IResult, IResultClosure, GetInput, GetOutput};
 pub use self::map::*;
@@ -43,6 +49,10 @@ pub use self::macros::*;
 pub use self::producer::*;//{ProducerState,Producer,FileProducer,MemProducer};
 pub use self::consumer::*;//{ConsumerState,Consumer};
 pub use self::nom::*;
+pub use self::precedence::*;
+pub use self::memo::*;
+pub use self::debug::*;
+pub use self::profile::*;
 
 pub mod util;
 pub mod internal;
@@ -51,4 +61,11 @@ pub mod internal;
 pub mod consumer;
 pub mod map;
 #[macro_use] pub mod nom;
+pub mod precedence;
+pub mod memo;
+pub mod debug;
+pub mod profile;
+
+#[cfg(all(test, unix))]
+mod test_util;
 
@@ -16,26 +16,350 @@ use internal::IResult::*;
 #[macro_export]
 macro_rules! tag(
   ($name:ident $inp:expr) => (
-    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]>{
-      #[inline(always)]
-      fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
-        b.as_bytes()
+    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(i:&[u8]) -> IResult<&[u8], &[u8]> {
+        #[inline(always)]
+        fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+          b.as_bytes()
+        }
+
+        let expected = $inp;
+        let bytes = as_bytes(&expected);
+
+        if bytes.len() > i.len() {
+          return Incomplete(Needed::Size(bytes.len() as u32));
+        }
+
+        if &i[0..bytes.len()] == bytes {
+          Done(&i[bytes.len()..], &i[0..bytes.len()])
+        } else {
+          Error($crate::Err::Code(0))
+        }
+      }
+      trace_parser!(stringify!($name), i, call!(i, inner))
+    }
+  )
+);
+
+/// calls a parser function or closure on the input
+///
+/// this is the glue used by combinators taking inline sub-parsers:
+/// a function name or closure given where a sub-parser is expected
+/// is wrapped in `call!`
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  let r = call!(b"abcdefgh", x);
+///  assert_eq!(r, Done(b"efgh", b"abcd"));
+/// ```
+#[macro_export]
+macro_rules! call(
+  ($i:expr, $fun:expr) => (
+    $fun($i)
+  );
+);
+
+/// offset of the remaining input of a sub-parser in the input it was given
+///
+/// if the sub-parser returned a slice that is not a part of its input, like a
+/// `b""` literal, the enclosing function returns a `ForeignInput` error. With a
+/// result variable, the error is stored in it and the enclosing loop is stopped
+#[doc(hidden)]
+#[macro_export]
+macro_rules! checked_offset(
+  ($input:expr, $remaining:expr) => (
+    match $crate::util::Offset::checked_offset($input, $remaining) {
+      Some(offset) => offset,
+      None         => return IResult::Error($crate::Err::ForeignInput)
+    }
+  );
+
+  ($input:expr, $remaining:expr, $ret:ident) => (
+    match $crate::util::Offset::checked_offset($input, $remaining) {
+      Some(offset) => offset,
+      None         => {
+        $ret = IResult::Error($crate::Err::ForeignInput);
+        break;
+      }
+    }
+  );
+);
+
+/// locates the error of a parser at the beginning of its input
+///
+/// the combinators applying parsers in sequence (`chain!`, `tuple!`, `count!`...) add
+/// the offset of the sub-parser's input, so the position of the error counts from the
+/// beginning of the outermost input
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  tag!(y "efgh");
+///  pair!(p<&[u8], (&[u8], &[u8])>, x, locate!(y));
+///
+///  assert_eq!(p(b"abcdabcd"), Error(Position(4, Box::new(Code(0)))));
+/// ```
+#[macro_export]
+macro_rules! locate(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, locate!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      let input = $i;
+      match $submac!(input, $($args)*) {
+        IResult::Error(e)      => IResult::Error(e.at(input, input)),
+        IResult::Incomplete(n) => IResult::Incomplete(n),
+        IResult::Done(i, o)    => IResult::Done(i, o)
+      }
+    }
+  );
+
+  ($i:expr, $f:expr) => (
+    locate!($i, call!($f))
+  );
+);
+
+/// maps a function on the result of a parser
+///
+/// ```ignore
+///  map!(len<&[u8], usize>, alpha, |s:&[u8]| s.len());
+///
+///  assert_eq!(len(b"abcd123"), Done(b"123", 4));
+/// ```
+#[macro_export]
+macro_rules! map(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, map!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)      => IResult::Error(e),
+      IResult::Incomplete(i) => IResult::Incomplete(i),
+      IResult::Done(i, o)    => IResult::Done(i, $g(o))
+    }
+  );
+
+  ($i:expr, $f:expr, $g:expr) => (
+    map!($i, call!($f), $g)
+  );
+);
+
+/// maps a function returning a Result on the output of a parser
+///
+/// if the function returns an error, it is kept in a `Conversion` error. The error type
+/// must implement `std::error::Error`: a function returning `Result<_, ()>` or
+/// `Result<_, &str>` can be used with `map_opt!` instead, as `|o| f(o).ok()`
+///
+/// ```ignore
+///  map_res!(name<&[u8], &str>, alpha, str::from_utf8);
+///
+///  assert_eq!(name(b"abcd123"), Done(b"123", "abcd"));
+///  assert_eq!(name(b"\xff123"), Error(Conversion(...)));
+///
+///  fn even(n: u8) -> Result<u8, ()> { if n % 2 == 0 { Ok(n) } else { Err(()) } }
+///  map_opt!(even_byte<&[u8], u8>, be_u8, |n| even(n).ok());
+/// ```
+#[macro_export]
+macro_rules! map_res(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, map_res!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)      => IResult::Error(e),
+      IResult::Incomplete(i) => IResult::Incomplete(i),
+      IResult::Done(i, o)    => match $g(o) {
+        Ok(output) => IResult::Done(i, output),
+        Err(e)     => IResult::Error($crate::Err::Conversion($crate::internal::ConversionError::new(e)))
       }
+    }
+  );
+
+  ($i:expr, $f:expr, $g:expr) => (
+    map_res!($i, call!($f), $g)
+  );
+);
 
-      let expected = $inp;
-      let bytes = as_bytes(&expected);
+/// maps a function returning an Option on the output of a parser
+///
+/// if the function returns None, an error is returned
+///
+/// ```ignore
+///  map_opt!(first<&[u8], u8>, alpha, |s:&[u8]| s.first().cloned());
+///
+///  assert_eq!(first(b"abcd123"), Done(b"123", b'a'));
+///  assert_eq!(first(b"123"), Error(Code(0)));
+/// ```
+#[macro_export]
+macro_rules! map_opt(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, map_opt!(input, $($rest)*))
+    }
+  );
 
-      if bytes.len() > i.len() {
-        return Incomplete(Needed::Size(bytes.len() as u32));
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)      => IResult::Error(e),
+      IResult::Incomplete(i) => IResult::Incomplete(i),
+      IResult::Done(i, o)    => match $g(o) {
+        Some(output) => IResult::Done(i, output),
+        None         => IResult::Error($crate::Err::Code(0))
       }
+    }
+  );
+
+  ($i:expr, $f:expr, $g:expr) => (
+    map_opt!($i, call!($f), $g)
+  );
+);
+
+/// applies a parser, then fails with a `Verify` error if its output does not satisfy the predicate
+///
+/// the predicate receives a reference to the output
+///
+/// ```ignore
+///  verify!(box_size<&[u8], u32>, be_u32, |s:&u32| *s >= 8);
+///
+///  let input = &b"\x00\x00\x00\x10"[..];
+///  assert_eq!(box_size(input), Done(&input[4..], 16));
+///  assert_eq!(box_size(b"\x00\x00\x00\x04"), Error(Verify));
+/// ```
+#[macro_export]
+macro_rules! verify(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, verify!(input, $($rest)*))
+    }
+  );
 
-      if &i[0..bytes.len()] == bytes {
-        Done(&i[bytes.len()..], &i[0..bytes.len()])
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)      => IResult::Error(e),
+      IResult::Incomplete(i) => IResult::Incomplete(i),
+      IResult::Done(i, o)    => if $g(&o) {
+        IResult::Done(i, o)
       } else {
-        Error(0)
+        IResult::Error($crate::Err::Verify)
       }
     }
-  )
+  );
+
+  ($i:expr, $f:expr, $g:expr) => (
+    verify!($i, call!($f), $g)
+  );
+);
+
+/// applies a parser and returns the provided value instead of its output
+///
+/// without a parser, returns the value without consuming anything
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  tag!(y "efgh");
+///  alt!(number<&[u8], u8>, value!(x, 1) | value!(y, 2) | value!(0));
+///
+///  assert_eq!(number(b"efghX"), Done(b"X", 2));
+///  assert_eq!(number(b"ijklX"), Done(b"ijklX", 0));
+/// ```
+#[macro_export]
+macro_rules! value(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, value!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* ), $res:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)      => IResult::Error(e),
+      IResult::Incomplete(i) => IResult::Incomplete(i),
+      IResult::Done(i, _)    => IResult::Done(i, $res)
+    }
+  );
+
+  ($i:expr, $f:expr, $res:expr) => (
+    value!($i, call!($f), $res)
+  );
+
+  ($i:expr, $res:expr) => (
+    IResult::Done($i, $res)
+  );
+);
+
+/// applies a parser and returns the slice of input it consumed, instead of its result
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  many0!(multi<&[u8], &[u8]> x);
+///  recognize!(raw<&[u8], &[u8]>, multi);
+///
+///  assert_eq!(raw(b"abcdabcdefgh"), Done(b"efgh", b"abcdabcd"));
+/// ```
+#[macro_export]
+macro_rules! recognize(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, recognize!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      let input = $i;
+      match $submac!(input, $($args)*) {
+        IResult::Done(i,_)     => match $crate::util::Offset::checked_offset(input, i) {
+          Some(index) => IResult::Done(i, &input[..index]),
+          None        => IResult::Error($crate::Err::ForeignInput)
+        },
+        IResult::Error(e)      => IResult::Error(e),
+        IResult::Incomplete(i) => IResult::Incomplete(i)
+      }
+    }
+  );
+
+  ($i:expr, $f:expr) => (
+    recognize!($i, call!($f))
+  );
+);
+
+/// applies the parser only if the condition is true, and returns an Option of its result
+///
+/// if the condition is false, returns `Done(input, None)` without consuming anything.
+/// If the condition is true, the errors of the parser are returned
+///
+/// ```ignore
+///  let r = cond!(input, version == 1, be_u64);
+/// ```
+#[macro_export]
+macro_rules! cond(
+  ($i:expr, $cond:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      let input = $i;
+      if $cond {
+        match $submac!(input, $($args)*) {
+          IResult::Done(i,o)     => IResult::Done(i, Some(o)),
+          IResult::Error(e)      => IResult::Error(e),
+          IResult::Incomplete(i) => IResult::Incomplete(i)
+        }
+      } else {
+        IResult::Done(input, None)
+      }
+    }
+  );
+
+  ($i:expr, $cond:expr, $f:expr) => (
+    cond!($i, $cond, call!($f))
+  );
 );
 
 /// chains parsers and returns the result of only one of them
@@ -49,21 +373,26 @@ macro_rules! tag(
 ///  // parse the x tag two times, return an int, parse the y tag
 ///  o!(z<&[u8], u8>  x ~ x ~ [ ret_int ] ~ y);
 ///
-/// let r = Done((), b"abcdabcdefgh").flat_map(z);
-/// assert_eq!(r, Done(b"", 1));
+/// let input = &b"abcdabcdefgh"[..];
+/// let r = Done((), input).flat_map(z);
+/// assert_eq!(r, Done(&input[12..], 1));
 /// ```
 #[macro_export]
 macro_rules! o(
   ($name:ident<$i:ty,$o:ty> $f1:ident ~ $($rest:tt)*) => (
     #[allow(unused_variables)]
-    fn $name(input:$i) -> IResult<$i, $o>{
-      match $f1(input) {
-        IResult::Error(e)      => IResult::Error(e),
-        IResult::Incomplete(i) => IResult::Incomplete(i),//IResult::Incomplete(i),
-        IResult::Done(i,o)     => {
-          o_parser!(i ~ o ~ $($rest)*)
+    fn $name(input:$i) -> IResult<$i, $o> {
+      #[allow(unused_variables)]
+      fn inner(input:$i) -> IResult<$i, $o> {
+        match $f1(input) {
+          IResult::Error(e)      => IResult::Error(e),
+          IResult::Incomplete(i) => IResult::Incomplete(i),//IResult::Incomplete(i),
+          IResult::Done(i,o)     => {
+            o_parser!(i ~ o ~ $($rest)*)
+          }
         }
       }
+      trace_parser!(stringify!($name), input, call!(input, inner))
     }
   );
 );
@@ -137,278 +466,1076 @@ macro_rules! o_parser(
 /// tag!(y "efgh");
 ///
 /// fn ret_int(i:&[u8]) -> IResult<&[u8], u8> { Done(i, 1) };
-/// fn ret_y(i:&[u8]) -> IResult<&[u8], u8> { y(i).map(|_| 1) }; // return 1 if the "efgh" tag is found
 ///
 ///  chain!(z<&[u8], u8>,
-///    x            ~
-///    aa: ret_int  ~     // the result of that parser will be used in the closure
-///    x?           ~     // this parser is optional
-///    bb: ret_y?   ,     // the result of that parser is an option
+///    x               ~
+///    aa: ret_int     ~     // the result of that parser will be used in the closure
+///    x?              ~     // this parser is optional
+///    bb: value!(y, 1)?,    // returns 1 if the "efgh" tag is found. The result is an option
 ///    ||{B{a: aa, b: bb}}
 ///  );
 ///
 /// // the first "abcd" tag is not present, we have an error
 /// let r1 = z(b"efgh");
-/// assert_eq!(r1, Error(0));
+/// assert_eq!(r1, Error($crate::Err::Code(0)));
 ///
 /// // everything is present, everything is parsed
-/// let r2 = z(b"abcdabcdefgh");
-/// assert_eq!(r2, Done(b"", B{a: 1, b: Some(1)}));
+/// // the remaining input is the end of the input
+/// let input2 = &b"abcdabcdefgh"[..];
+/// assert_eq!(z(input2), Done(&input2[12..], B{a: 1, b: Some(1)}));
 ///
 /// // the second "abcd" tag is optional
-/// let r3 = z(b"abcdefgh");
-/// assert_eq!(r3, Done(b"", B{a: 1, b: Some(1)}));
+/// let input3 = &b"abcdefgh"[..];
+/// assert_eq!(z(input3), Done(&input3[8..], B{a: 1, b: Some(1)}));
 ///
-/// // the result of ret_y is optional, as seen in the B structure
-/// let r4 = z(b"abcdabcd");
-/// assert_eq!(r4, Done(b"", B{a: 1, b: None}));
+/// // the result of value!(y, 1) is optional, as seen in the B structure
+/// let input4 = &b"abcdabcd"[..];
+/// assert_eq!(z(input4), Done(&input4[8..], B{a: 1, b: None}));
 /// ```
+///
+/// the steps can also be inline macro calls, and their arguments can use
+/// the results of the previous steps:
+///
+/// ```ignore
+///  chain!(data<&[u8], (&[u8], Option<u8>)>,
+///    len:   be_u8               ~
+///    bytes: take!(len as usize) ~
+///    extra: cond!(len > 2, be_u8),
+///    ||{(bytes, extra)}
+///  );
+///
+///  assert_eq!(data(b"\x02abX"), Done(b"X", (b"ab", None)));
+///  let input = &b"\x03abcX"[..];
+///  assert_eq!(data(input), Done(&input[5..], (b"abc", Some(88))));
+/// ```
+///
+/// chain! can also be used directly in an expression: `chain!(input, x ~ aa: ret_int, ||{aa})`
 #[macro_export]
 macro_rules! chain (
   ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
     #[allow(unused_variables)]
     fn $name(i:$i) -> IResult<$i,$o>{
-      chaining_parser!(i, $($rest)*)
+      trace_parser!(stringify!($name), i, chaining_parser_impl!(i, i, $($rest)*))
+    }
+  );
+
+  ($i:expr, $($rest:tt)*) => (
+    {
+      let input = $i;
+      chaining_parser_impl!(input, input, $($rest)*)
     }
   );
 );
 
+/// applies the steps of a `chain!` to the input
+///
+/// the results of the steps are bound to their names for the closure
+/// assembling the result
 #[macro_export]
 macro_rules! chaining_parser (
-  ($i:expr, $e:ident ~ $($rest:tt)*) => (
+  ($i:expr, $($rest:tt)*) => (
+    {
+      let input = $i;
+      chaining_parser_impl!(input, input, $($rest)*)
+    }
+  );
+);
+
+#[macro_export]
+macro_rules! chaining_parser_impl (
+  ($start:expr, $i:expr, $submac:ident!( $($args:tt)* ) ~ $($rest:tt)*) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,_)                   => {
+        chaining_parser_impl!($start, i, $($rest)*)
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $submac:ident!( $($args:tt)* ) ? ~ $($rest:tt)*) => (
+    match $submac!($i, $($args)*) {
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(_)                    => {
+        chaining_parser_impl!($start, $i, $($rest)*)
+      },
+      IResult::Done(i,_)                   => {
+        chaining_parser_impl!($start, i, $($rest)*)
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $field:ident : $submac:ident!( $($args:tt)* ) ~ $($rest:tt)*) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,o)                   => {
+        let $field = o;
+        chaining_parser_impl!($start, i, $($rest)*)
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $field:ident : $submac:ident!( $($args:tt)* ) ? ~ $($rest:tt)*) => (
+    match $submac!($i, $($args)*) {
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(_)                    => {
+        let $field = None;
+        chaining_parser_impl!($start, $i, $($rest)*)
+      },
+      IResult::Done(i,o)                   => {
+        let $field = Some(o);
+        chaining_parser_impl!($start, i, $($rest)*)
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $e:ident ~ $($rest:tt)*) => (
     match $e($i) {
-      IResult::Error(e)      => IResult::Error(e),
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Done(i,_)     => {
-        chaining_parser!(i, $($rest)*)
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,_)                   => {
+        chaining_parser_impl!($start, i, $($rest)*)
       }
     }
   );
 
-  ($i:expr, $e:ident ? ~ $($rest:tt)*) => (
+  ($start:expr, $i:expr, $e:ident ? ~ $($rest:tt)*) => (
     match $e($i) {
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Error(e)      => {
-        chaining_parser!($i, $($rest)*)
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(e)                    => {
+        chaining_parser_impl!($start, $i, $($rest)*)
       },
-      IResult::Done(i,_)     => {
-        chaining_parser!(i, $($rest)*)
+      IResult::Done(i,_)                   => {
+        chaining_parser_impl!($start, i, $($rest)*)
       }
     }
   );
 
-  ($i:expr, $field:ident : $e:ident ~ $($rest:tt)*) => (
+  ($start:expr, $i:expr, $field:ident : $e:ident ~ $($rest:tt)*) => (
     match $e($i) {
-      IResult::Error(e)      => IResult::Error(e),
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Done(i,o)     => {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,o)                   => {
         let $field = o;
-        chaining_parser!(i, $($rest)*)
+        chaining_parser_impl!($start, i, $($rest)*)
       }
     }
   );
 
-  ($i:expr, $field:ident : $e:ident ? ~ $($rest:tt)*) => (
+  ($start:expr, $i:expr, $field:ident : $e:ident ? ~ $($rest:tt)*) => (
     match $e($i) {
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Error(e)      => {
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(e)                    => {
         let $field = None;
-        chaining_parser!($i, $($rest)*)
+        chaining_parser_impl!($start, $i, $($rest)*)
       },
-      IResult::Done(i,o)     => {
+      IResult::Done(i,o)                   => {
         let $field = Some(o);
-        chaining_parser!(i, $($rest)*)
+        chaining_parser_impl!($start, i, $($rest)*)
       }
     }
   );
 
   // ending the chain
-  ($i:expr, $e:ident, $assemble:expr) => (
-    match $e($i) {
-      IResult::Error(e)      => IResult::Error(e),
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Done(i,_)     => {
+  ($start:expr, $i:expr, $submac:ident!( $($args:tt)* ), $assemble:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,_)                   => {
         IResult::Done(i, $assemble())
       }
     }
   );
 
-  ($i:expr, $e:ident ?, $assemble:expr) => (
-    match $e($i) {
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Error(e)      => {
+  ($start:expr, $i:expr, $submac:ident!( $($args:tt)* ) ?, $assemble:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(_)                    => {
         IResult::Done($i, $assemble())
       },
-      IResult::Done(i,_)     => {
+      IResult::Done(i,_)                   => {
         IResult::Done(i, $assemble())
       }
     }
   );
 
-  ($i:expr, $field:ident : $e:ident, $assemble:expr) => (
-    match $e($i) {
-      IResult::Error(e)      => IResult::Error(e),
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Done(i,o)     => {
+  ($start:expr, $i:expr, $field:ident : $submac:ident!( $($args:tt)* ), $assemble:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,o)                   => {
         let $field = o;
         IResult::Done(i, $assemble())
       }
     }
   );
 
-  ($i:expr, $field:ident : $e:ident ? , $assemble:expr) => (
-    match $e($i) {
-      IResult::Incomplete(i) => IResult::Incomplete(i),
-      IResult::Error(e)      => {
+  ($start:expr, $i:expr, $field:ident : $submac:ident!( $($args:tt)* ) ?, $assemble:expr) => (
+    match $submac!($i, $($args)*) {
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(_)                    => {
         let $field = None;
         IResult::Done($i, $assemble())
       },
-      IResult::Done(i,o)     => {
+      IResult::Done(i,o)                   => {
         let $field = Some(o);
         IResult::Done(i, $assemble())
       }
     }
   );
 
-  ($i:expr, $assemble:expr) => (
-    IResult::Done($i, $assemble())
-  )
-);
-
-/// try a list of parser, return the result of the first successful one
+  ($start:expr, $i:expr, $e:ident, $assemble:expr) => (
+    match $e($i) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,_)                   => {
+        IResult::Done(i, $assemble())
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $e:ident ?, $assemble:expr) => (
+    match $e($i) {
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(e)                    => {
+        IResult::Done($i, $assemble())
+      },
+      IResult::Done(i,_)                   => {
+        IResult::Done(i, $assemble())
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $field:ident : $e:ident, $assemble:expr) => (
+    match $e($i) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,o)                   => {
+        let $field = o;
+        IResult::Done(i, $assemble())
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $field:ident : $e:ident ? , $assemble:expr) => (
+    match $e($i) {
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Error(e)                    => {
+        let $field = None;
+        IResult::Done($i, $assemble())
+      },
+      IResult::Done(i,o)                   => {
+        let $field = Some(o);
+        IResult::Done(i, $assemble())
+      }
+    }
+  );
+
+  ($start:expr, $i:expr, $assemble:expr) => (
+    IResult::Done($i, $assemble())
+  )
+);
+
+/// applies a list of parsers in sequence and returns a tuple of their results
+///
+/// the sub-parsers can be functions, closures, or inline macro calls
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  tag!(y "efgh");
+///  tuple!(z<&[u8], (&[u8], &[u8], &[u8])>, x, y, x);
+///
+///  assert_eq!(z(b"abcdefghabcdX"), Done(b"X", (b"abcd", b"efgh", b"abcd")));
+///  assert_eq!(z(b"abcdabcd"), Error($crate::Err::Code(0)));
+///  assert_eq!(z(b"abcdefghab"), Incomplete(Needed::Size(12)));
+/// ```
+#[macro_export]
+macro_rules! tuple (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, tuple!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $($rest:tt)*) => (
+    {
+      let input = $i;
+      tuple_parser!(input, input, (), $($rest)*)
+    }
+  );
+);
+
+#[macro_export]
+macro_rules! tuple_parser (
+  ($start:expr, $i:expr, ($($parsed:tt)*), $submac:ident!( $($args:tt)* ), $($rest:tt)*) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,o)                   => {
+        tuple_parser!($start, i, ($($parsed)* o,), $($rest)*)
+      }
+    }
+  );
+  ($start:expr, $i:expr, ($($parsed:tt)*), $e:expr, $($rest:tt)*) => (
+    tuple_parser!($start, $i, ($($parsed)*), call!($e), $($rest)*)
+  );
+  ($start:expr, $i:expr, ($($parsed:tt)*), $submac:ident!( $($args:tt)* )) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($start, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($start, $i) as u32 + n)),
+      IResult::Done(i,o)                   => {
+        IResult::Done(i, ($($parsed)* o,))
+      }
+    }
+  );
+  ($start:expr, $i:expr, ($($parsed:tt)*), $e:expr) => (
+    tuple_parser!($start, $i, ($($parsed)*), call!($e))
+  );
+);
+
+/// applies two parsers in sequence and returns a pair of their results
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  tag!(y "efgh");
+///  pair!(z<&[u8], (&[u8], &[u8])>, x, y);
+///
+///  assert_eq!(z(b"abcdefghX"), Done(b"X", (b"abcd", b"efgh")));
+/// ```
+#[macro_export]
+macro_rules! pair (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, pair!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $($rest:tt)*) => (
+    match tuple!($i, $($rest)*) {
+      IResult::Error(e)          => IResult::Error(e),
+      IResult::Incomplete(n)     => IResult::Incomplete(n),
+      IResult::Done(i, (o1, o2)) => IResult::Done(i, (o1, o2))
+    }
+  );
+);
+
+/// applies two parsers in sequence and returns the result of the second one
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  tag!(y "efgh");
+///  preceded!(z<&[u8], &[u8]>, x, y);
+///
+///  assert_eq!(z(b"abcdefghX"), Done(b"X", b"efgh"));
+/// ```
+#[macro_export]
+macro_rules! preceded (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, preceded!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $($rest:tt)*) => (
+    match tuple!($i, $($rest)*) {
+      IResult::Error(e)         => IResult::Error(e),
+      IResult::Incomplete(n)    => IResult::Incomplete(n),
+      IResult::Done(i, (_, o))  => IResult::Done(i, o)
+    }
+  );
+);
+
+/// applies two parsers in sequence and returns the result of the first one
 ///
-/// Incomplete results are ignored
+/// ```ignore
+///  tag!(x "abcd");
+///  tag!(y "efgh");
+///  terminated!(z<&[u8], &[u8]>, x, y);
+///
+///  assert_eq!(z(b"abcdefghX"), Done(b"X", b"abcd"));
+/// ```
+#[macro_export]
+macro_rules! terminated (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, terminated!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $($rest:tt)*) => (
+    match tuple!($i, $($rest)*) {
+      IResult::Error(e)         => IResult::Error(e),
+      IResult::Incomplete(n)    => IResult::Incomplete(n),
+      IResult::Done(i, (o, _))  => IResult::Done(i, o)
+    }
+  );
+);
+
+/// applies three parsers in sequence and returns the result of the second one
+///
+/// ```ignore
+///  tag!(lsb "[");
+///  tag!(rsb "]");
+///  delimited!(bracketed<&[u8], &[u8]>, lsb, alpha, rsb);
+///
+///  assert_eq!(bracketed(b"[abcd]X"), Done(b"X", b"abcd"));
+/// ```
+#[macro_export]
+macro_rules! delimited (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, delimited!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $($rest:tt)*) => (
+    match tuple!($i, $($rest)*) {
+      IResult::Error(e)           => IResult::Error(e),
+      IResult::Incomplete(n)      => IResult::Incomplete(n),
+      IResult::Done(i, (_, o, _)) => IResult::Done(i, o)
+    }
+  );
+);
+
+/// applies three parsers in sequence and returns a pair of the results of the first and third ones
+///
+/// ```ignore
+///  tag!(equal "=");
+///  separated_pair!(key_value<&[u8], (&[u8], &[u8])>, alpha, equal, alpha);
+///
+///  assert_eq!(key_value(b"abc=def;"), Done(b";", (b"abc", b"def")));
+/// ```
+#[macro_export]
+macro_rules! separated_pair (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, separated_pair!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $($rest:tt)*) => (
+    match tuple!($i, $($rest)*) {
+      IResult::Error(e)             => IResult::Error(e),
+      IResult::Incomplete(n)        => IResult::Incomplete(n),
+      IResult::Done(i, (o1, _, o2)) => IResult::Done(i, (o1, o2))
+    }
+  );
+);
+
+/// try a list of parser, return the result of the first successful one
+///
+/// If a parser returns Incomplete, alt! returns Incomplete: the data is not
+/// available to decide if this branch would succeed, so the next ones are not tried.
+///
+/// If every parser fails, the error holds the errors of all the branches, in order
 ///
 /// ```ignore
 ///  tag!(x "abcd");
 ///  tag!(y "efgh");
-///  alt!(test<&[u8], &[u8]> x | y);
+///  alt!(test<&[u8], &[u8]>, x | y);
 ///  let r1 = test(b"abcdefgh"));
 ///  assert_eq!(r1, Done(b"efgh", b"abcd"));
 ///  let r2 = test(b"efghijkl"));
 ///  assert_eq!(r2, Done(b"ijkl", b"efgh"));
+///  let r3 = test(b"ijklmnop"));
+///  assert_eq!(r3, Error(Alt(vec![Code(0), Code(0)])));
+///  let r4 = test(b"ef"));
+///  assert_eq!(r4, Incomplete(Needed::Size(4)));
 /// ```
 #[macro_export]
 macro_rules! alt (
   ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
     fn $name(i:$i) -> IResult<$i,$o>{
-      alt_parser!(i | $($rest)*)
+      trace_parser!(stringify!($name), i, alt!(i, $($rest)*))
+    }
+  );
+
+  ($i:expr, $($rest:tt)*) => (
+    {
+      let input      = $i;
+      let mut errors = Vec::new();
+      alt_parser!(input, errors | $($rest)*)
     }
   );
 );
 
 #[macro_export]
 macro_rules! alt_parser (
-  ($i:ident | $e:ident | $($rest:tt)*) => (
-    match $e($i) {
-      IResult::Error(_)      => alt_parser!($i | $($rest)*),
-      IResult::Incomplete(_) => alt_parser!($i | $($rest)*),
+  ($i:expr, $errors:ident | $submac:ident!( $($args:tt)* ) | $($rest:tt)*) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)      => {
+        $errors.push(e);
+        alt_parser!($i, $errors | $($rest)*)
+      },
+      IResult::Incomplete(n) => IResult::Incomplete(n),
       IResult::Done(i,o)     => IResult::Done(i,o)
     }
   );
 
-  ($i:ident | $e:ident) => (
-    match $e($i) {
-      IResult::Error(_)      => alt_parser!($i),
-      IResult::Incomplete(_) => alt_parser!($i),
+  ($i:expr, $errors:ident | $e:ident | $($rest:tt)*) => (
+    alt_parser!($i, $errors | call!($e) | $($rest)*)
+  );
+
+  ($i:expr, $errors:ident | $submac:ident!( $($args:tt)* )) => (
+    match $submac!($i, $($args)*) {
+      IResult::Error(e)      => {
+        $errors.push(e);
+        IResult::Error($crate::Err::Alt($errors))
+      },
+      IResult::Incomplete(n) => IResult::Incomplete(n),
       IResult::Done(i,o)     => IResult::Done(i,o)
     }
   );
 
-  ($i:ident) => (
-    IResult::Error(1)
+  ($i:expr, $errors:ident | $e:ident) => (
+    alt_parser!($i, $errors | call!($e))
+  );
+);
+
+/// applies a selector parser, then a sub-parser chosen from the selector's result
+///
+/// the arms are patterns on the selector's output, and inline parsers. If the
+/// chosen parser fails, the other arms are not tried. A last `_` arm is the default,
+/// without it a `Switch` error is returned if no arm matches
+///
+/// ```ignore
+///  take!(box_type 4);
+///  switch!(box_body<&[u8], u32>, call!(box_type),
+///    b"ftyp" => call!(be_u32)
+///  | b"free" => call!(ret_zero)
+///  );
+///
+///  assert_eq!(box_body(b"ftyp\x00\x00\x00\x01"), Done(b"", 1));
+///  assert_eq!(box_body(b"mdat\x00\x00\x00\x01"), Error(Switch));
+/// ```
+#[macro_export]
+macro_rules! switch (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, switch!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* ), $($arms:tt)* ) => (
+    {
+      let input = $i;
+      match $submac!(input, $($args)*) {
+        IResult::Error(e)      => IResult::Error(e.relocate(input, input)),
+        IResult::Incomplete(n) => IResult::Incomplete(n),
+        IResult::Done(i, o)    => switch_arms!(input, i, o, [], $($arms)*)
+      }
+    }
+  );
+
+  ($i:expr, $f:expr, $($rest:tt)*) => (
+    switch!($i, call!($f), $($rest)*)
+  );
+);
+
+/// builds the match of the arms of a `switch!`, and adds a `Switch` error
+/// if the last arm is not a default one
+#[macro_export]
+macro_rules! switch_arms (
+  ($input:expr, $i:expr, $o:expr, [$($acc:tt)*], _ => $subrule:ident!( $($args:tt)* )) => (
+    match $o {
+      $($acc)*
+      _ => switch_arm!($input, $i, $subrule!($($args)*))
+    }
+  );
+
+  ($input:expr, $i:expr, $o:expr, [$($acc:tt)*], $p:pat => $subrule:ident!( $($args:tt)* )) => (
+    match $o {
+      $($acc)*
+      $p => switch_arm!($input, $i, $subrule!($($args)*)),
+      _  => IResult::Error($crate::Err::Switch)
+    }
+  );
+
+  ($input:expr, $i:expr, $o:expr, [$($acc:tt)*], $p:pat => $subrule:ident!( $($args:tt)* ) | $($rest:tt)*) => (
+    switch_arms!($input, $i, $o, [$($acc)* $p => switch_arm!($input, $i, $subrule!($($args)*)),], $($rest)*)
+  );
+);
+
+/// applies the parser of the chosen arm of a `switch!` after the selector
+#[macro_export]
+macro_rules! switch_arm (
+  ($input:expr, $i:expr, $subrule:ident!( $($args:tt)* )) => (
+    match $subrule!($i, $($args)*) {
+      IResult::Error(e)                    => IResult::Error(e.relocate($input, $i)),
+      IResult::Incomplete(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+      IResult::Incomplete(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset($input, $i) as u32 + n)),
+      IResult::Done(i2, o2)                => IResult::Done(i2, o2)
+    }
+  );
+);
+
+/// applies each parser exactly once, in any order, and returns a tuple of their results in declaration order
+///
+/// a member followed by `?` is optional, and its result is an Option. The parsers are
+/// tried in declaration order at each position, until no remaining parser succeeds.
+/// If a required member was not found, the error is `Missing(position)`, or
+/// `Duplicate(position)` if an already parsed member appears again instead.
+/// At the end of the input, the members not found yet could still follow, so the
+/// permutation returns Incomplete, unless they are applied with complete!.
+/// Up to 20 members are supported
+///
+/// ```ignore
+///  tag!(a "a");
+///  tag!(b "b");
+///  tag!(c "c");
+///  permutation!(perm<&[u8], (&[u8], Option<&[u8]>, &[u8])>, a, b?, c);
+///
+///  assert_eq!(perm(b"cbaX"), Done(b"X", (b"a", Some(b"b"), b"c")));
+///  assert_eq!(perm(b"caX"),  Done(b"X", (b"a", None, b"c")));
+///  assert_eq!(perm(b"cbX"),  Error(Missing(0)));
+///  assert_eq!(perm(b"cbcX"), Error(Duplicate(2)));
+/// ```
+#[macro_export]
+macro_rules! permutation (
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, permutation!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $($rest:tt)*) => (
+    permutation_normalize!($i, (), $($rest)*)
+  );
+);
+
+/// internal parser, do not use directly
+///
+/// rewrites the members of a permutation as `(req submac!(...))` or `(opt submac!(...))`
+#[macro_export]
+macro_rules! permutation_normalize (
+  ($i:expr, ($($acc:tt)*), $e:ident ?, $($rest:tt)*) => (
+    permutation_normalize!($i, ($($acc)* (opt call!($e))), $($rest)*)
+  );
+  ($i:expr, ($($acc:tt)*), $e:ident ?) => (
+    permutation_impl!($i, $($acc)* (opt call!($e)))
+  );
+  ($i:expr, ($($acc:tt)*), $e:ident, $($rest:tt)*) => (
+    permutation_normalize!($i, ($($acc)* (req call!($e))), $($rest)*)
+  );
+  ($i:expr, ($($acc:tt)*), $e:ident) => (
+    permutation_impl!($i, $($acc)* (req call!($e)))
+  );
+  ($i:expr, ($($acc:tt)*), $submac:ident!( $($args:tt)* ) ?, $($rest:tt)*) => (
+    permutation_normalize!($i, ($($acc)* (opt $submac!($($args)*))), $($rest)*)
+  );
+  ($i:expr, ($($acc:tt)*), $submac:ident!( $($args:tt)* ) ?) => (
+    permutation_impl!($i, $($acc)* (opt $submac!($($args)*)))
+  );
+  ($i:expr, ($($acc:tt)*), $submac:ident!( $($args:tt)* ), $($rest:tt)*) => (
+    permutation_normalize!($i, ($($acc)* (req $submac!($($args)*))), $($rest)*)
+  );
+  ($i:expr, ($($acc:tt)*), $submac:ident!( $($args:tt)* )) => (
+    permutation_impl!($i, $($acc)* (req $submac!($($args)*)))
+  );
+);
+
+/// internal parser, do not use directly
+#[macro_export]
+macro_rules! permutation_impl (
+  ($i:expr, $($members:tt)*) => (
+    {
+      let start = $i;
+      let mut input = start;
+      let mut res = permutation_init!((), $($members)*);
+      let mut incomplete: Option<Needed> = None;
+      loop {
+        let mut found = false;
+        permutation_iterator!(0, input, found, incomplete, res, $($members)*);
+        if !found || incomplete.is_some() {
+          break;
+        }
+      }
+
+      match incomplete {
+        Some(Needed::Unknown) => IResult::Incomplete(Needed::Unknown),
+        Some(Needed::Size(n)) => IResult::Incomplete(Needed::Size($crate::util::Offset::offset(start, input) as u32 + n)),
+        None                  => {
+          let mut missing: Option<usize> = None;
+          permutation_missing!(0, res, missing, $($members)*);
+          match missing {
+            Some(m) => {
+              let mut duplicate: Option<usize> = None;
+              permutation_duplicate!(0, input, res, duplicate, $($members)*);
+              match duplicate {
+                Some(d) => IResult::Error($crate::Err::Duplicate(d)),
+                None    => IResult::Error($crate::Err::Missing(m))
+              }
+            },
+            None    => IResult::Done(input, permutation_unwrap!(0, res, (), $($members)*))
+          }
+        }
+      }
+    }
+  );
+);
+
+/// internal parser, do not use directly
+#[macro_export]
+macro_rules! permutation_init (
+  (($($acc:tt)*), ($kind:ident $($member:tt)*) $($rest:tt)*) => (
+    permutation_init!(($($acc)* None,), $($rest)*)
+  );
+  (($($acc:tt)*),) => (
+    ($($acc)*)
+  );
+);
+
+/// internal parser, do not use directly
+///
+/// tries the members that were not found yet, until one of them succeeds
+#[macro_export]
+macro_rules! permutation_iterator (
+  ($it:tt, $i:ident, $found:ident, $incomplete:ident, $res:ident, ($kind:ident $submac:ident!( $($args:tt)* )) $($rest:tt)*) => (
+    if !$found && $incomplete.is_none() && $res.$it.is_none() {
+      match $submac!($i, $($args)*) {
+        IResult::Done(i,o)     => {
+          $res.$it = Some(o);
+          $i       = i;
+          $found   = true;
+        },
+        IResult::Error(_)      => {},
+        IResult::Incomplete(n) => {
+          $incomplete = Some(n);
+        }
+      }
+    }
+    succ!($it, permutation_iterator!($i, $found, $incomplete, $res, $($rest)*));
+  );
+  ($it:tt, $i:ident, $found:ident, $incomplete:ident, $res:ident,) => ();
+);
+
+/// internal parser, do not use directly
+///
+/// finds the first required member that was not found
+#[macro_export]
+macro_rules! permutation_missing (
+  ($it:tt, $res:ident, $missing:ident, (req $($member:tt)*) $($rest:tt)*) => (
+    if $missing.is_none() && $res.$it.is_none() {
+      $missing = Some($it);
+    }
+    succ!($it, permutation_missing!($res, $missing, $($rest)*));
+  );
+  ($it:tt, $res:ident, $missing:ident, (opt $($member:tt)*) $($rest:tt)*) => (
+    succ!($it, permutation_missing!($res, $missing, $($rest)*));
+  );
+  ($it:tt, $res:ident, $missing:ident,) => ();
+);
+
+/// internal parser, do not use directly
+///
+/// finds the first member already found that would succeed again
+#[macro_export]
+macro_rules! permutation_duplicate (
+  ($it:tt, $i:ident, $res:ident, $duplicate:ident, ($kind:ident $submac:ident!( $($args:tt)* )) $($rest:tt)*) => (
+    if $duplicate.is_none() && $res.$it.is_some() {
+      if let IResult::Done(_,_) = $submac!($i, $($args)*) {
+        $duplicate = Some($it);
+      }
+    }
+    succ!($it, permutation_duplicate!($i, $res, $duplicate, $($rest)*));
+  );
+  ($it:tt, $i:ident, $res:ident, $duplicate:ident,) => ();
+);
+
+/// internal parser, do not use directly
+///
+/// builds the result tuple, unwrapping the required members
+#[macro_export]
+macro_rules! permutation_unwrap (
+  ($it:tt, $res:ident, ($($acc:tt)*), (req $($member:tt)*) $($rest:tt)*) => (
+    succ!($it, permutation_unwrap!($res, ($($acc)* $res.$it.unwrap(),), $($rest)*))
+  );
+  ($it:tt, $res:ident, ($($acc:tt)*), (opt $($member:tt)*) $($rest:tt)*) => (
+    succ!($it, permutation_unwrap!($res, ($($acc)* $res.$it,), $($rest)*))
+  );
+  ($it:tt, $res:ident, ($($acc:tt)*),) => (
+    ($($acc)*)
+  );
+);
+
+/// internal macro, do not use directly
+///
+/// calls a macro with the next index as first argument
+#[macro_export]
+macro_rules! succ (
+  (0, $submac:ident ! ($($rest:tt)*)) => ($submac!(1, $($rest)*));
+  (1, $submac:ident ! ($($rest:tt)*)) => ($submac!(2, $($rest)*));
+  (2, $submac:ident ! ($($rest:tt)*)) => ($submac!(3, $($rest)*));
+  (3, $submac:ident ! ($($rest:tt)*)) => ($submac!(4, $($rest)*));
+  (4, $submac:ident ! ($($rest:tt)*)) => ($submac!(5, $($rest)*));
+  (5, $submac:ident ! ($($rest:tt)*)) => ($submac!(6, $($rest)*));
+  (6, $submac:ident ! ($($rest:tt)*)) => ($submac!(7, $($rest)*));
+  (7, $submac:ident ! ($($rest:tt)*)) => ($submac!(8, $($rest)*));
+  (8, $submac:ident ! ($($rest:tt)*)) => ($submac!(9, $($rest)*));
+  (9, $submac:ident ! ($($rest:tt)*)) => ($submac!(10, $($rest)*));
+  (10, $submac:ident ! ($($rest:tt)*)) => ($submac!(11, $($rest)*));
+  (11, $submac:ident ! ($($rest:tt)*)) => ($submac!(12, $($rest)*));
+  (12, $submac:ident ! ($($rest:tt)*)) => ($submac!(13, $($rest)*));
+  (13, $submac:ident ! ($($rest:tt)*)) => ($submac!(14, $($rest)*));
+  (14, $submac:ident ! ($($rest:tt)*)) => ($submac!(15, $($rest)*));
+  (15, $submac:ident ! ($($rest:tt)*)) => ($submac!(16, $($rest)*));
+  (16, $submac:ident ! ($($rest:tt)*)) => ($submac!(17, $($rest)*));
+  (17, $submac:ident ! ($($rest:tt)*)) => ($submac!(18, $($rest)*));
+  (18, $submac:ident ! ($($rest:tt)*)) => ($submac!(19, $($rest)*));
+  (19, $submac:ident ! ($($rest:tt)*)) => ($submac!(20, $($rest)*));
+);
+
+/// returns the longest list of bytes that do not appear in the provided array
+///
+/// ```ignore
+///  is_not!(not_space b" \t\r\n");
+///  let r = not_space(b"abcdefgh\nijkl"));
+///  assert_eq!(r, Done(b"\nijkl", b"abcdefgh"));
+/// ```
+#[macro_export]
+macro_rules! is_not(
+  ($name:ident $arr:expr) => (
+    fn $name(input:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(input:&[u8]) -> IResult<&[u8], &[u8]> {
+        #[inline(always)]
+        fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+          b.as_bytes()
+        }
+
+        let expected = $arr;
+        let bytes = as_bytes(&expected);
+
+        for idx in 0..input.len() {
+          for &i in bytes.iter() {
+            if input[idx] == i {
+              return IResult::Done(&input[idx..], &input[0..idx])
+            }
+          }
+        }
+        IResult::Done(&input[input.len()..], input)
+      }
+      trace_parser!(stringify!($name), input, call!(input, inner))
+    }
+  )
+);
+
+/// returns the longest list of bytes that appear in the provided array
+///
+/// ```ignore
+///  is_a!(abcd b"abcd");
+///  let r1 = abcd(b"aaaaefgh"));
+///  assert_eq!(r1, Done(b"efgh", b"aaaa"));
+///
+///  let r2 = abcd(b"dcbaefgh"));
+///  assert_eq!(r2, Done(b"efgh", b"dcba"));
+/// ```
+#[macro_export]
+macro_rules! is_a(
+  ($name:ident $arr:expr) => (
+    fn $name(input:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(input:&[u8]) -> IResult<&[u8], &[u8]> {
+        #[inline(always)]
+        fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+          b.as_bytes()
+        }
+
+        let expected = $arr;
+        let bytes = as_bytes(&expected);
+
+        for idx in 0..input.len() {
+          let mut res = false;
+          for &i in bytes.iter() {
+            if input[idx] == i {
+              res = true;
+              break;
+            }
+          }
+          if !res {
+            return IResult::Done(&input[idx..], &input[0..idx])
+          }
+        }
+        IResult::Done(&input[input.len()..], input)
+      }
+      trace_parser!(stringify!($name), input, call!(input, inner))
+    }
+  )
+);
+
+/// returns the longest list of bytes until the provided parser fails
+///
+/// if the whole input matches, it is returned entirely, so filter! is meant for complete
+/// data. `take_while!` returns `Incomplete` in that case
+///
+/// ```ignore
+///  filter!(alpha is_alphanumeric);
+///  let r = alpha(b"abcd\nefgh"));
+///  assert_eq!(r, Done(b"\nefgh", b"abcd"));
+/// ```
+#[macro_export]
+macro_rules! filter(
+  ($name:ident $f:ident) => (
+    fn $name(input:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(input:&[u8]) -> IResult<&[u8], &[u8]> {
+        for idx in 0..input.len() {
+          if !$f(input[idx]) {
+            return IResult::Done(&input[idx..], &input[0..idx])
+          }
+        }
+        IResult::Done(&input[input.len()..], input)
+      }
+      trace_parser!(stringify!($name), input, call!(input, inner))
+    }
   )
 );
 
-/// returns the longest list of bytes that do not appear in the provided array
+/// returns the longest list of bytes for which the function returns true
+///
+/// the function can be a closure or a function taking a byte. If the end of the input
+/// is reached, more data could match, so `Incomplete` is returned. The match can be empty
+///
+/// ```ignore
+///  take_while!(lower<&[u8], &[u8]>, |c| c >= b'a' && c <= b'z');
+///
+///  assert_eq!(lower(b"abcD"), Done(b"D", b"abc"));
+///  assert_eq!(lower(b"ABC"), Done(b"ABC", b""));
+///  assert_eq!(lower(b"abc"), Incomplete(Needed::Size(4)));
+/// ```
+#[macro_export]
+macro_rules! take_while(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, take_while!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $f:expr) => (
+    {
+      let input = $i;
+      match input.iter().position(|c| !($f)(*c)) {
+        Some(n) => IResult::Done(&input[n..], &input[..n]),
+        None    => IResult::Incomplete(Needed::Size(input.len() as u32 + 1))
+      }
+    }
+  );
+);
+
+/// like `take_while!`, but the match cannot be empty
 ///
 /// ```ignore
-///  is_not!(not_space b" \t\r\n");
-///  let r = not_space(b"abcdefgh\nijkl"));
-///  assert_eq!(r, Done(b"\nijkl", b"abcdefgh"));
+///  take_while1!(lower<&[u8], &[u8]>, |c| c >= b'a' && c <= b'z');
+///
+///  assert_eq!(lower(b"abcD"), Done(b"D", b"abc"));
+///  assert_eq!(lower(b"ABC"), Error(Code(0)));
+///  assert_eq!(lower(b""), Incomplete(Needed::Size(1)));
 /// ```
 #[macro_export]
-macro_rules! is_not(
-  ($name:ident $arr:expr) => (
-    fn $name(input:&[u8]) -> IResult<&[u8], &[u8]> {
-      #[inline(always)]
-      fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
-        b.as_bytes()
-      }
-
-      let expected = $arr;
-      let bytes = as_bytes(&expected);
+macro_rules! take_while1(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, take_while1!(input, $($rest)*))
+    }
+  );
 
-      for idx in 0..input.len() {
-        for &i in bytes.iter() {
-          if input[idx] == i {
-            return IResult::Done(&input[idx..], &input[0..idx])
-          }
-        }
+  ($i:expr, $f:expr) => (
+    {
+      let input = $i;
+      match input.iter().position(|c| !($f)(*c)) {
+        Some(0) => IResult::Error($crate::Err::Code(0)),
+        Some(n) => IResult::Done(&input[n..], &input[..n]),
+        None    => IResult::Incomplete(Needed::Size(input.len() as u32 + 1))
       }
-      IResult::Done(b"", input)
     }
-  )
+  );
 );
 
-/// returns the longest list of bytes that appear in the provided array
+/// returns between m and n bytes for which the function returns true
+///
+/// stops after n bytes, so it does not need more data once n bytes matched. Returns
+/// an error if less than m bytes match
 ///
 /// ```ignore
-///  is_a!(abcd b"abcd");
-///  let r1 = abcd(b"aaaaefgh"));
-///  assert_eq!(r1, Done(b"efgh", b"aaaa"));
+///  take_while_m_n!(hex<&[u8], &[u8]>, 2, 4, |c| (c as char).is_digit(16));
 ///
-///  let r2 = abcd(b"dcbaefgh"));
-///  assert_eq!(r2, Done(b"efgh", b"dcba"));
+///  assert_eq!(hex(b"1f;"), Done(b";", b"1f"));
+///  assert_eq!(hex(b"12345"), Done(b"5", b"1234"));
+///  assert_eq!(hex(b"1;"), Error(Code(0)));
+///  assert_eq!(hex(b"12"), Incomplete(Needed::Size(3)));
 /// ```
 #[macro_export]
-macro_rules! is_a(
-  ($name:ident $arr:expr) => (
-    fn $name(input:&[u8]) -> IResult<&[u8], &[u8]> {
-      #[inline(always)]
-      fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
-        b.as_bytes()
-      }
-
-      let expected = $arr;
-      let bytes = as_bytes(&expected);
+macro_rules! take_while_m_n(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, take_while_m_n!(input, $($rest)*))
+    }
+  );
 
-      for idx in 0..input.len() {
-        let mut res = false;
-        for &i in bytes.iter() {
-          if input[idx] == i {
-            res = true;
-            break;
+  ($i:expr, $m:expr, $n:expr, $f:expr) => (
+    {
+      let input = $i;
+      let m     = $m as usize;
+      let n     = $n as usize;
+      let max   = if input.len() < n { input.len() } else { n };
+      match input[..max].iter().position(|c| !($f)(*c)) {
+        Some(idx) => {
+          if idx < m {
+            IResult::Error($crate::Err::Code(0))
+          } else {
+            IResult::Done(&input[idx..], &input[..idx])
+          }
+        },
+        None      => {
+          if max == n {
+            IResult::Done(&input[n..], &input[..n])
+          } else {
+            IResult::Incomplete(Needed::Size(max as u32 + 1))
           }
-        }
-        if !res {
-          return IResult::Done(&input[idx..], &input[0..idx])
         }
       }
-      IResult::Done(b"", input)
     }
-  )
+  );
 );
 
-/// returns the longest list of bytes until the provided parser fails
+/// returns the longest list of bytes until the function returns true
+///
+/// the byte for which it returns true is left in the remaining input. If the end of the
+/// input is reached, `Incomplete` is returned
 ///
 /// ```ignore
-///  filter!(alpha is_alphanumeric);
-///  let r = alpha(b"abcd\nefgh"));
-///  assert_eq!(r, Done(b"\nefgh", b"abcd"));
+///  take_till!(line<&[u8], &[u8]>, |c| c == b'\n');
+///
+///  assert_eq!(line(b"abc\ndef"), Done(b"\ndef", b"abc"));
+///  assert_eq!(line(b"abc"), Incomplete(Needed::Size(4)));
 /// ```
 #[macro_export]
-macro_rules! filter(
-  ($name:ident $f:ident) => (
-    fn $name(input:&[u8]) -> IResult<&[u8], &[u8]> {
-      for idx in 0..input.len() {
-        if !$f(input[idx]) {
-          return IResult::Done(&input[idx..], &input[0..idx])
-        }
+macro_rules! take_till(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, take_till!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $f:expr) => (
+    {
+      let input = $i;
+      match input.iter().position(|c| ($f)(*c)) {
+        Some(n) => IResult::Done(&input[n..], &input[..n]),
+        None    => IResult::Incomplete(Needed::Size(input.len() as u32 + 1))
       }
-      IResult::Done(b"", input)
     }
-  )
+  );
 );
 
 /// make the underlying parser optional
@@ -428,11 +1555,14 @@ macro_rules! filter(
 macro_rules! opt(
   ($name:ident<$i:ty,$o:ty> $f:ident) => (
     fn $name(input:$i) -> IResult<$i, Option<$o>> {
-      match $f(input) {
-        IResult::Done(i,o)     => IResult::Done(i, Some(o)),
-        IResult::Error(_)      => IResult::Done(input, None),
-        IResult::Incomplete(i) => IResult::Incomplete(i)
+      fn inner(input:$i) -> IResult<$i, Option<$o>> {
+        match $f(input) {
+          IResult::Done(i,o)     => IResult::Done(i, Some(o)),
+          IResult::Error(_)      => IResult::Done(input, None),
+          IResult::Incomplete(i) => IResult::Incomplete(i)
+        }
       }
+      trace_parser!(stringify!($name), input, call!(input, inner))
     }
   )
 );
@@ -446,33 +1576,186 @@ macro_rules! opt(
 ///  peek!(ptag<&[u8], &[u8]> x);
 ///  let r = ptag(b"abcdefgh"));
 ///  assert_eq!(r, Done(b"abcdefgh", b"abcd"));
+///
+///  // it can also be used inline
+///  let r2 = peek!(&b"abcdefgh"[..], x);
 /// ```
 #[macro_export]
 macro_rules! peek(
   ($name:ident<$i:ty,$o:ty> $f:ident) => (
     fn $name(input:$i) -> IResult<$i, $o> {
-      match $f(input) {
-        IResult::Done(i,o)     => IResult::Done(input, o),
+      trace_parser!(stringify!($name), input, peek!(input, $f))
+    }
+  );
+
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i, $o> {
+      trace_parser!(stringify!($name), input, peek!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      let input = $i;
+      match $submac!(input, $($args)*) {
+        IResult::Done(_,o)     => IResult::Done(input, o),
         IResult::Error(a)      => IResult::Error(a),
         IResult::Incomplete(i) => IResult::Incomplete(i)
       }
     }
-  )
+  );
+
+  ($i:expr, $f:expr) => (
+    peek!($i, call!($f))
+  );
+);
+
+/// succeeds without consuming the input if the embedded parser fails, and fails if it succeeds
+///
+/// if the embedded parser returns Incomplete, not! cannot decide and returns Incomplete too
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  tag!(y "efgh");
+///  // "abcd" not followed by "efgh"
+///  terminated!(alone<&[u8], &[u8]>, x, not!(y));
+///
+///  assert_eq!(alone(b"abcdabcd"), Done(b"abcd", b"abcd"));
+///  assert_eq!(alone(b"abcdefgh"), Error(Code(0)));
+///  assert_eq!(alone(b"abcdef"), Incomplete(Needed::Size(8)));
+/// ```
+#[macro_export]
+macro_rules! not(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, not!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      let input = $i;
+      match $submac!(input, $($args)*) {
+        IResult::Done(_,_)     => IResult::Error($crate::Err::Code(0)),
+        IResult::Error(_)      => IResult::Done(input, ()),
+        IResult::Incomplete(i) => IResult::Incomplete(i)
+      }
+    }
+  );
+
+  ($i:expr, $f:expr) => (
+    not!($i, call!($f))
+  );
+);
+
+/// applies a parser to complete data, like a whole file or the last chunk of a producer
+///
+/// repetitions like many0! return Incomplete when the input ends, since more items could
+/// follow. complete! turns Incomplete into an `UnexpectedEnd` error, so the repetition stops
+/// at the end of the data
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  complete!(cx<&[u8], &[u8]>, x);
+///  many0!(multi<&[u8], &[u8]> cx);
+///
+///  let input = &b"abcdabcd"[..];
+///  assert_eq!(multi(input), Done(&input[8..], vec![&b"abcd"[..], &b"abcd"[..]]));
+///  assert_eq!(cx(b"ab"), Error(UnexpectedEnd(Needed::Size(4))));
+/// ```
+#[macro_export]
+macro_rules! complete(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, complete!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* )) => (
+    match $submac!($i, $($args)*) {
+      IResult::Done(i,o)     => IResult::Done(i, o),
+      IResult::Error(e)      => IResult::Error(e),
+      IResult::Incomplete(n) => IResult::Error($crate::Err::UnexpectedEnd(n))
+    }
+  );
+
+  ($i:expr, $f:expr) => (
+    complete!($i, call!($f))
+  );
+);
+
+/// succeeds only at the end of the data, and returns the empty remaining input
+///
+/// a parser cannot know if more data will come after an empty input: eof! returns
+/// Incomplete on an empty input, unless its argument is true. That argument tells that
+/// the input is the end of the data, like a whole file or the last chunk given by a producer
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  terminated!(whole<&[u8], &[u8]>, x, eof!(true));
+///
+///  let input = &b"abcd"[..];
+///  assert_eq!(whole(input), Done(&input[4..], b"abcd"));
+///  assert_eq!(whole(b"abcdef"), Error(Code(0)));
+///
+///  // more data could follow
+///  terminated!(partial<&[u8], &[u8]>, x, eof!());
+///  assert_eq!(partial(input), Incomplete(Needed::Size(5)));
+///
+///  eof!(end<&[u8], &[u8]>, true);
+///  assert_eq!(end(&input[4..]), Done(&input[4..], &input[4..]));
+/// ```
+#[macro_export]
+macro_rules! eof(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, eof!(input, $($rest)*))
+    }
+  );
+
+  ($name:ident<$i:ty,$o:ty>) => (
+    eof!($name<$i,$o>,);
+  );
+
+  ($i:expr,) => (
+    eof!($i, false)
+  );
+
+  ($i:expr) => (
+    eof!($i, false)
+  );
+
+  ($i:expr, $last:expr) => (
+    {
+      let input = $i;
+      if input.len() != 0 {
+        IResult::Error($crate::Err::Code(0))
+      } else if $last {
+        IResult::Done(input, input)
+      } else {
+        // one more byte would tell that it is not the end
+        IResult::Incomplete(Needed::Size(1))
+      }
+    }
+  );
 );
 
 /// Applies the parser 0 or more times and returns the list of results in a Vec
 ///
-/// the embedded parser may return Incomplete
+/// the embedded parser may return Incomplete, in which case the whole list returns Incomplete.
+/// It happens at the end of the input too, since more items could follow: use complete!
+/// on the embedded parser to parse complete data.
+/// If the embedded parser succeeds without consuming anything, an error is returned
 ///
 /// ```ignore
 ///  tag!(x "abcd");
 ///  many0!(multi<&[u8],&[u8]> x);
 ///
-///  let a = b"abcdabcdef";
+///  let a = b"abcdabcdefgh";
 ///  let b = b"azerty";
 ///
 ///  let res = vec![b"abcd", b"abcd"];
-///  assert_eq!(multi(a), Done(b"ef", res));
+///  assert_eq!(multi(a), Done(b"efgh", res));
 ///  assert_eq!(multi(b), Done(b"azerty", Vec::new()));
 /// ```
 // 0 or more
@@ -480,75 +1763,419 @@ macro_rules! peek(
 macro_rules! many0(
   ($name:ident<$i:ty,$o:ty> $f:ident) => (
     fn $name(input:$i) -> IResult<$i,Vec<$o>> {
-      let mut begin = 0;
-      let mut remaining = input.len();
-      let mut res: Vec<$o> = Vec::new();
+      fn inner(input:$i) -> IResult<$i,Vec<$o>> {
+        let mut begin = 0;
+        let mut res: Vec<$o> = Vec::new();
+        loop {
+          match $f(&input[begin..]) {
+            IResult::Done(i,o) => {
+              let offset = checked_offset!(input, i);
+              if offset == begin {
+                // the parser consumed nothing, we would loop forever
+                return IResult::Error($crate::Err::Code(0))
+              }
+              res.push(o);
+              begin = offset;
+            },
+            IResult::Error(_)                    => {
+              return IResult::Done(&input[begin..], res)
+            },
+            IResult::Incomplete(Needed::Unknown) => {
+              return IResult::Incomplete(Needed::Unknown)
+            },
+            IResult::Incomplete(Needed::Size(n)) => {
+              return IResult::Incomplete(Needed::Size(begin as u32 + n))
+            }
+          }
+        }
+      }
+      trace_parser!(stringify!($name), input, call!(input, inner))
+    }
+  )
+);
+
+/// Applies the parser 1 or more times and returns the list of results in a Vec
+///
+/// the embedded parser may return Incomplete, in which case the whole list returns Incomplete.
+/// It happens at the end of the input too, since more items could follow: use complete!
+/// on the embedded parser to parse complete data.
+/// If the embedded parser succeeds without consuming anything, an error is returned
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  many1!(multi<&[u8],&[u8]> x);
+///
+///  let a = b"abcdabcdefgh";
+///  let b = b"azerty";
+///
+///  let res = vec![b"abcd", b"abcd"];
+///  assert_eq!(multi(a), Done(b"efgh", res));
+///  assert_eq!(multi(b), Error($crate::Err::Code(0)));
+/// ```
+#[macro_export]
+macro_rules! many1(
+  ($name:ident<$i:ty,$o:ty> $f:ident) => (
+    fn $name(input:$i) -> IResult<$i,Vec<$o>> {
+      fn inner(input:$i) -> IResult<$i,Vec<$o>> {
+        let mut begin = 0;
+        let mut res: Vec<$o> = Vec::new();
+        loop {
+          match $f(&input[begin..]) {
+            IResult::Done(i,o) => {
+              let offset = checked_offset!(input, i);
+              if offset == begin {
+                // the parser consumed nothing, we would loop forever
+                return IResult::Error($crate::Err::Code(0))
+              }
+              res.push(o);
+              begin = offset;
+            },
+            IResult::Error(e)                    => {
+              if begin == 0 {
+                return IResult::Error(e)
+              } else {
+                return IResult::Done(&input[begin..], res)
+              }
+            },
+            IResult::Incomplete(Needed::Unknown) => {
+              return IResult::Incomplete(Needed::Unknown)
+            },
+            IResult::Incomplete(Needed::Size(n)) => {
+              return IResult::Incomplete(Needed::Size(begin as u32 + n))
+            }
+          }
+        }
+      }
+      trace_parser!(stringify!($name), input, call!(input, inner))
+    }
+  )
+);
+
+/// Applies the parser exactly n times and returns the list of results in a Vec
+///
+/// n can be computed at runtime, like a count read earlier in the input. If the parser
+/// succeeds without consuming anything, an error is returned
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  count!(multi<&[u8],&[u8]>, x, 2);
+///
+///  let a = b"abcdabcdabcdef";
+///  let b = b"abcdefgh";
+///
+///  let res = vec![b"abcd", b"abcd"];
+///  assert_eq!(multi(a), Done(b"abcdef", res));
+///  assert_eq!(multi(b), Error($crate::Err::Code(0)));
+///
+///  // a count read from the input
+///  fn counted(input:&[u8]) -> IResult<&[u8], Vec<&[u8]>> {
+///    match be_u8(input) {
+///      Done(i, nb)   => count!(i, x, nb as usize),
+///      Error(e)      => Error(e),
+///      Incomplete(n) => Incomplete(n)
+///    }
+///  }
+/// ```
+#[macro_export]
+macro_rules! count(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,Vec<$o>> {
+      trace_parser!(stringify!($name), input, count!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $count:expr) => (
+    {
+      let input     = $i;
+      let count     = $count;
+      let mut begin = 0;
+      let mut res   = Vec::with_capacity(count);
+      let ret;
+
+      loop {
+        if res.len() == count {
+          ret = IResult::Done(&input[begin..], res);
+          break;
+        }
+
+        match $submac!(&input[begin..], $($args)*) {
+          IResult::Done(i,o)                   => {
+            let offset = checked_offset!(input, i, ret);
+            if offset == begin {
+              // the parser consumed nothing
+              ret = IResult::Error($crate::Err::Code(0));
+              break;
+            }
+            res.push(o);
+            begin = offset;
+          },
+          IResult::Error(e)                    => {
+            ret = IResult::Error(e.relocate(input, &input[begin..]));
+            break;
+          },
+          IResult::Incomplete(Needed::Unknown) => {
+            ret = IResult::Incomplete(Needed::Unknown);
+            break;
+          },
+          IResult::Incomplete(Needed::Size(n)) => {
+            ret = IResult::Incomplete(Needed::Size(begin as u32 + n));
+            break;
+          }
+        }
+      }
+      ret
+    }
+  );
+  ($i:expr, $f:expr, $count:expr) => (
+    count!($i, call!($f), $count)
+  );
+);
+
+/// Applies the parser exactly n times and returns the results in a fixed size array
+///
+/// n must be a constant, and the output type must implement Copy and Default. If the
+/// parser succeeds without consuming anything, an error is returned
+///
+/// ```ignore
+///  count_fixed!(three<&[u8],u16>, be_u16, 3);
+///
+///  let a = [0, 1, 0, 2, 0, 3, 4];
+///  assert_eq!(three(&a), Done(&a[6..], [1, 2, 3]));
+///
+///  // inline, with the output type as second argument
+///  let r = count_fixed!(&a[..], u8, be_u8, 2);
+///  assert_eq!(r, Done(&a[2..], [0, 1]));
+/// ```
+#[macro_export]
+macro_rules! count_fixed(
+  ($name:ident<$i:ty,$o:ty>, $f:expr, $count:expr) => (
+    fn $name(input:$i) -> IResult<$i,[$o; $count]> {
+      trace_parser!(stringify!($name), input, count_fixed!(input, $o, $f, $count))
+    }
+  );
+  ($i:expr, $typ:ty, $submac:ident!( $($args:tt)* ), $count:expr) => (
+    {
+      let input                      = $i;
+      let mut begin                  = 0;
+      let mut filled                 = 0;
+      let mut res: [$typ; $count]    = [Default::default(); $count];
+      let ret;
+
+      loop {
+        if filled == $count {
+          ret = IResult::Done(&input[begin..], res);
+          break;
+        }
+
+        match $submac!(&input[begin..], $($args)*) {
+          IResult::Done(i,o)                   => {
+            let offset = checked_offset!(input, i, ret);
+            if offset == begin {
+              // the parser consumed nothing
+              ret = IResult::Error($crate::Err::Code(0));
+              break;
+            }
+            res[filled] = o;
+            filled     += 1;
+            begin       = offset;
+          },
+          IResult::Error(e)                    => {
+            ret = IResult::Error(e.relocate(input, &input[begin..]));
+            break;
+          },
+          IResult::Incomplete(Needed::Unknown) => {
+            ret = IResult::Incomplete(Needed::Unknown);
+            break;
+          },
+          IResult::Incomplete(Needed::Size(n)) => {
+            ret = IResult::Incomplete(Needed::Size(begin as u32 + n));
+            break;
+          }
+        }
+      }
+      ret
+    }
+  );
+  ($i:expr, $typ:ty, $f:expr, $count:expr) => (
+    count_fixed!($i, $typ, call!($f), $count)
+  );
+);
+
+/// Applies the parser between m and n times (n included) and returns the list of results in a Vec
+///
+/// returns an error if the parser succeeds less than m times, if it
+/// succeeds without consuming anything, or if m is larger than n.
+/// At the end of the input, it returns Incomplete while less than n items were parsed
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  many_m_n!(multi<&[u8],&[u8]>, 2, 3, x);
+///
+///  let a = b"abcdefgh";
+///  let b = b"abcdabcdefgh";
+///  let c = b"abcdabcdabcdabcdefgh";
+///
+///  assert_eq!(multi(a), Error($crate::Err::Code(0)));
+///  let res1 = vec![b"abcd", b"abcd"];
+///  assert_eq!(multi(b), Done(b"efgh", res1));
+///  let res2 = vec![b"abcd", b"abcd", b"abcd"];
+///  assert_eq!(multi(c), Done(b"abcdefgh", res2));
+/// ```
+#[macro_export]
+macro_rules! many_m_n(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,Vec<$o>> {
+      trace_parser!(stringify!($name), input, many_m_n!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $m:expr, $n:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      let input         = $i;
+      let m: usize      = $m;
+      let n: usize      = $n;
+      let mut begin     = 0;
+      let mut res       = Vec::with_capacity(m);
+      let ret;
+
       loop {
-        match $f(&input[begin..]) {
-          IResult::Done(i,o) => {
-            res.push(o);
-            begin += remaining - i.len();
-            remaining = i.len();
-            if begin >= input.len() {
-              return IResult::Done(i, res)
+        if m > n {
+          ret = IResult::Error($crate::Err::Code(0));
+          break;
+        }
+        if res.len() == n {
+          ret = IResult::Done(&input[begin..], res);
+          break;
+        }
+
+        match $submac!(&input[begin..], $($args)*) {
+          IResult::Done(i,o)                   => {
+            let offset = checked_offset!(input, i, ret);
+            if offset == begin {
+              // the parser consumed nothing
+              ret = IResult::Error($crate::Err::Code(0));
+              break;
             }
+            res.push(o);
+            begin = offset;
+          },
+          IResult::Error(e)                    => {
+            ret = if res.len() < m {
+              IResult::Error(e.relocate(input, &input[begin..]))
+            } else {
+              IResult::Done(&input[begin..], res)
+            };
+            break;
+          },
+          IResult::Incomplete(Needed::Unknown) => {
+            ret = IResult::Incomplete(Needed::Unknown);
+            break;
           },
-          _                  => {
-            return IResult::Done(&input[begin..], res)
+          IResult::Incomplete(Needed::Size(n)) => {
+            ret = IResult::Incomplete(Needed::Size(begin as u32 + n));
+            break;
           }
         }
       }
+      ret
     }
-  )
+  );
+  ($i:expr, $m:expr, $n:expr, $f:expr) => (
+    many_m_n!($i, $m, $n, call!($f))
+  );
 );
 
-/// Applies the parser 0 or more times and returns the list of results in a Vec
+/// Applies the first parser until the second one succeeds, and returns a pair of
+/// the list of results of the first parser, and the result of the second one
 ///
-/// the embedded parser may return Incomplete
+/// returns an error if the first parser fails before the second one succeeds,
+/// or if it succeeds without consuming anything
 ///
 /// ```ignore
 ///  tag!(x "abcd");
-///  many1!(multi<&[u8],&[u8]> x);
+///  tag!(end "end");
+///  many_till!(multi<&[u8],&[u8],&[u8]>, x, end);
 ///
-///  let a = b"abcdabcdef";
-///  let b = b"azerty";
+///  let a = b"abcdabcdendefgh";
+///  let b = b"abcdefgh";
 ///
-///  let res = vec![b"abcd", b"abcd"];
-///  assert_eq!(multi(a), Done(b"ef", res));
-///  assert_eq!(multi(b), Error(0));
+///  let res = (vec![b"abcd", b"abcd"], b"end");
+///  assert_eq!(multi(a), Done(b"efgh", res));
+///  assert_eq!(multi(b), Error($crate::Err::Code(0)));
 /// ```
 #[macro_export]
-macro_rules! many1(
-  ($name:ident<$i:ty,$o:ty> $f:ident) => (
-    fn $name(input:$i) -> IResult<$i,Vec<$o>> {
-      let mut begin = 0;
-      let mut remaining = input.len();
-      let mut res: Vec<$o> = Vec::new();
+macro_rules! many_till(
+  ($name:ident<$i:ty,$o:ty,$p:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,(Vec<$o>, $p)> {
+      trace_parser!(stringify!($name), input, many_till!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $submac2:ident!( $($args2:tt)* )) => (
+    {
+      let input         = $i;
+      let mut begin     = 0;
+      let mut res       = Vec::new();
+      let ret;
+
       loop {
-        match $f(&input[begin..]) {
-          IResult::Done(i,o) => {
-            res.push(o);
-            begin += remaining - i.len();
-            remaining = i.len();
-            if begin >= input.len() {
-              return IResult::Done(i, res)
-            }
+        match $submac2!(&input[begin..], $($args2)*) {
+          IResult::Done(i,o)                   => {
+            ret = IResult::Done(i, (res, o));
+            break;
           },
-          _                  => {
-            if begin == 0 {
-              return IResult::Error(0)
-            } else {
-            return IResult::Done(&input[begin..], res)
+          IResult::Incomplete(Needed::Unknown) => {
+            ret = IResult::Incomplete(Needed::Unknown);
+            break;
+          },
+          IResult::Incomplete(Needed::Size(n)) => {
+            ret = IResult::Incomplete(Needed::Size(begin as u32 + n));
+            break;
+          },
+          IResult::Error(_)                    => {
+            match $submac!(&input[begin..], $($args)*) {
+              IResult::Done(i,o)                   => {
+                let offset = checked_offset!(input, i, ret);
+                if offset == begin {
+                  // the parser consumed nothing
+                  ret = IResult::Error($crate::Err::Code(0));
+                  break;
+                }
+                res.push(o);
+                begin = offset;
+              },
+              IResult::Error(e)                    => {
+                ret = IResult::Error(e.relocate(input, &input[begin..]));
+                break;
+              },
+              IResult::Incomplete(Needed::Unknown) => {
+                ret = IResult::Incomplete(Needed::Unknown);
+                break;
+              },
+              IResult::Incomplete(Needed::Size(n)) => {
+                ret = IResult::Incomplete(Needed::Size(begin as u32 + n));
+                break;
+              }
             }
           }
         }
       }
+      ret
     }
-  )
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    many_till!($i, $submac!($($args)*), call!($g))
+  );
+  ($i:expr, $f:expr, $submac:ident!( $($args:tt)* )) => (
+    many_till!($i, call!($f), $submac!($($args)*))
+  );
+  ($i:expr, $f:expr, $g:expr) => (
+    many_till!($i, call!($f), call!($g))
+  );
 );
 
 /// takes an assembling closure, and a parser, and generates a fold on the input 0 or more times
 ///
+/// like many0!, Incomplete from the parser is returned as is, and a parser
+/// succeeding without consuming anything makes the fold fail
+///
 /// for the parser `fn p(i:I) -> IResult<I,T>` and the usage `fold0!(f<I,O>, |a,b| { ... }, p)`, this macro generates
 /// `fn f(input:I, z: O) -> IResult<I,O>`
 /// The closure takes as argument a value of type O and a value of type T and returns a value of type T
@@ -557,7 +2184,10 @@ macro_rules! many1(
 macro_rules! fold0(
   ($name:ident<$i:ty,$o:ty>, $assemble:expr, $f:ident) => (
     fn $name(input:$i, z:$o) -> IResult<$i,$o> {
-      fold0_impl!(<$i, $o>, $assemble, $f, input, z);
+      fn inner(input:$i, z:$o) -> IResult<$i,$o> {
+        fold0_impl!(<$i, $o>, $assemble, $f, input, z);
+      }
+      trace_parser!(stringify!($name), input, call!(input, move |i| inner(i, z)))
     }
   );
 );
@@ -567,21 +2197,27 @@ macro_rules! fold0_impl(
   (<$i:ty,$o:ty>, $assemble:expr, $f:ident, $input:ident, $z:ident) => (
     {
       let mut begin = 0;
-      let mut remaining = $input.len();
       let mut res: $o = $z;
       loop {
         match $f(&$input[begin..]) {
           IResult::Done(i,o) => {
+            let offset = checked_offset!($input, i);
+            if offset == begin {
+              // the parser consumed nothing, we would loop forever
+              return IResult::Error($crate::Err::Code(0))
+            }
             //res.push(o);
             res = $assemble(res, o);
-            begin += remaining - i.len();
-            remaining = i.len();
-            if begin >= $input.len() {
-              return IResult::Done(i, res)
-            }
+            begin = offset;
           },
-          _                  => {
+          IResult::Error(_)                    => {
             return IResult::Done(&$input[begin..], res)
+          },
+          IResult::Incomplete(Needed::Unknown) => {
+            return IResult::Incomplete(Needed::Unknown)
+          },
+          IResult::Incomplete(Needed::Size(n)) => {
+            return IResult::Incomplete(Needed::Size(begin as u32 + n))
           }
         }
       }
@@ -591,6 +2227,9 @@ macro_rules! fold0_impl(
 
 /// takes an assembling closure, and a parser, and generates a fold on the input 1 or more times
 ///
+/// like many1!, Incomplete from the parser is returned as is, and a parser
+/// succeeding without consuming anything makes the fold fail
+///
 /// for the parser `fn p(i:I) -> IResult<I,T>` and the usage `fold0!(f<I,O>, |a,b| { ... }, p)`, this macro generates
 /// `fn f(input:I, z: O) -> IResult<I,O>`
 /// The closure takes as argument a value of type O and a value of type T and returns a value of type T
@@ -599,7 +2238,10 @@ macro_rules! fold0_impl(
 macro_rules! fold1(
   ($name:ident<$i:ty,$o:ty>, $assemble:expr, $f:ident) => (
     fn $name(input:$i, z:$o) -> IResult<$i,$o> {
-      fold1_impl!(<$i, $o>, $assemble, $f, input, z);
+      fn inner(input:$i, z:$o) -> IResult<$i,$o> {
+        fold1_impl!(<$i, $o>, $assemble, $f, input, z);
+      }
+      trace_parser!(stringify!($name), input, call!(input, move |i| inner(i, z)))
     }
   );
 );
@@ -609,28 +2251,231 @@ macro_rules! fold1_impl(
   (<$i:ty,$o:ty>, $assemble:expr, $f:ident, $input:ident, $z:ident) => (
     {
       let mut begin = 0;
-      let mut remaining = $input.len();
       let mut res: $o = $z;
       loop {
         match $f(&$input[begin..]) {
           IResult::Done(i,o) => {
+            let offset = checked_offset!($input, i);
+            if offset == begin {
+              // the parser consumed nothing, we would loop forever
+              return IResult::Error($crate::Err::Code(0))
+            }
             //res.push(o);
             res = $assemble(res, o);
-            begin += remaining - i.len();
-            remaining = i.len();
-            if begin >= $input.len() {
-              return IResult::Done(i, res)
-            }
+            begin = offset;
           },
-          _                  => {
+          IResult::Error(e)                    => {
             if begin == 0 {
-              return IResult::Error(0)
+              return IResult::Error(e)
             } else {
               return IResult::Done(&$input[begin..], res)
             }
+          },
+          IResult::Incomplete(Needed::Unknown) => {
+            return IResult::Incomplete(Needed::Unknown)
+          },
+          IResult::Incomplete(Needed::Size(n)) => {
+            return IResult::Incomplete(Needed::Size(begin as u32 + n))
+          }
+        }
+      }
+    }
+  );
+);
+
+/// applies the element parser, separated by the separator parser, 0 or more times,
+/// and returns the list of elements in a Vec
+///
+/// the separator's result is discarded. If the element parser fails after a separator,
+/// the separator is not consumed. If the sub-parsers return Incomplete, so does the list,
+/// which happens at the end of the input since the list could continue.
+/// The list returns an error if an element and its separator consume nothing
+///
+/// ```ignore
+///  tag!(comma ",");
+///  tag!(abcd "abcd");
+///  separated_list!(list<&[u8],&[u8]>, comma, abcd);
+///
+///  assert_eq!(list(b"abcd,abcd;"), Done(b";", vec![b"abcd", b"abcd"]));
+///  assert_eq!(list(b"abcd,efgh"), Done(b",efgh", vec![b"abcd"]));
+///
+///  // the separator was found, but the next element is cut
+///  assert_eq!(list(b"abcd,ab"), Incomplete(Needed::Size(9)));
+///  // the input ends after an element, a separator could follow
+///  assert_eq!(list(b"abcd"), Incomplete(Needed::Size(5)));
+///  assert_eq!(list(b"efgh"), Done(b"efgh", Vec::new()));
+/// ```
+#[macro_export]
+macro_rules! separated_list(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i, Vec<$o>> {
+      trace_parser!(stringify!($name), input, separated_list!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $submac2:ident!( $($args2:tt)* )) => (
+    separated_list_impl!($i, false, false, $submac!($($args)*), $submac2!($($args2)*))
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    separated_list!($i, $submac!($($args)*), call!($g))
+  );
+  ($i:expr, $f:expr, $submac:ident!( $($args:tt)* )) => (
+    separated_list!($i, call!($f), $submac!($($args)*))
+  );
+  ($i:expr, $f:expr, $g:expr) => (
+    separated_list!($i, call!($f), call!($g))
+  );
+);
+
+/// applies the element parser, separated by the separator parser, 1 or more times,
+/// and returns the list of elements in a Vec
+///
+/// same as `separated_list!`, but returns an error if there is no element
+///
+/// ```ignore
+///  tag!(comma ",");
+///  tag!(abcd "abcd");
+///  separated_nonempty_list!(list<&[u8],&[u8]>, comma, abcd);
+///
+///  assert_eq!(list(b"abcd,abcd;"), Done(b";", vec![b"abcd", b"abcd"]));
+///  assert_eq!(list(b"efgh"), Error($crate::Err::Code(0)));
+/// ```
+#[macro_export]
+macro_rules! separated_nonempty_list(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i, Vec<$o>> {
+      trace_parser!(stringify!($name), input, separated_nonempty_list!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $submac2:ident!( $($args2:tt)* )) => (
+    separated_list_impl!($i, true, false, $submac!($($args)*), $submac2!($($args2)*))
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    separated_nonempty_list!($i, $submac!($($args)*), call!($g))
+  );
+  ($i:expr, $f:expr, $submac:ident!( $($args:tt)* )) => (
+    separated_nonempty_list!($i, call!($f), $submac!($($args)*))
+  );
+  ($i:expr, $f:expr, $g:expr) => (
+    separated_nonempty_list!($i, call!($f), call!($g))
+  );
+);
+
+/// applies the element parser, separated by the separator parser, 0 or more times,
+/// and accepts a separator after the last element
+///
+/// same as `separated_list!`, but a trailing separator is consumed
+///
+/// ```ignore
+///  tag!(comma ",");
+///  tag!(abcd "abcd");
+///  separated_list_trailing!(list<&[u8],&[u8]>, comma, abcd);
+///
+///  assert_eq!(list(b"abcd,abcd,;;;;"), Done(b";;;;", vec![b"abcd", b"abcd"]));
+///  assert_eq!(list(b"abcd,abcd;"), Done(b";", vec![b"abcd", b"abcd"]));
+///
+///  // another element could follow the trailing separator
+///  assert_eq!(list(b"abcd,abcd,"), Incomplete(Needed::Size(14)));
+/// ```
+#[macro_export]
+macro_rules! separated_list_trailing(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i, Vec<$o>> {
+      trace_parser!(stringify!($name), input, separated_list_trailing!(input, $($rest)*))
+    }
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $submac2:ident!( $($args2:tt)* )) => (
+    separated_list_impl!($i, false, true, $submac!($($args)*), $submac2!($($args2)*))
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    separated_list_trailing!($i, $submac!($($args)*), call!($g))
+  );
+  ($i:expr, $f:expr, $submac:ident!( $($args:tt)* )) => (
+    separated_list_trailing!($i, call!($f), $submac!($($args)*))
+  );
+  ($i:expr, $f:expr, $g:expr) => (
+    separated_list_trailing!($i, call!($f), call!($g))
+  );
+);
+
+#[macro_export]
+macro_rules! separated_list_impl(
+  ($i:expr, $nonempty:expr, $trailing:expr, $sep:ident!( $($args:tt)* ), $submac:ident!( $($args2:tt)* )) => (
+    {
+      let input       = $i;
+      let mut res     = Vec::new();
+      let mut begin   = 0;
+      let ret;
+
+      // the first element does not need a separator
+      match $submac!(&input[begin..], $($args2)*) {
+        IResult::Error(e)      => {
+          ret = if $nonempty { IResult::Error(e) } else { IResult::Done(&input[begin..], res) };
+        },
+        IResult::Incomplete(i) => {
+          ret = IResult::Incomplete(i);
+        },
+        IResult::Done(i,o)     => match $crate::util::Offset::checked_offset(input, i) {
+          None                            => {
+            ret = IResult::Error($crate::Err::ForeignInput);
+          },
+          Some(offset) if offset == begin => {
+            ret = IResult::Error($crate::Err::Code(0));
+          },
+          Some(offset)                    => {
+            res.push(o);
+            begin = offset;
+
+            loop {
+              match $sep!(&input[begin..], $($args)*) {
+                IResult::Error(_)                    => {
+                  ret = IResult::Done(&input[begin..], res);
+                  break;
+                },
+                IResult::Incomplete(Needed::Unknown) => {
+                  ret = IResult::Incomplete(Needed::Unknown);
+                  break;
+                },
+                IResult::Incomplete(Needed::Size(n)) => {
+                  ret = IResult::Incomplete(Needed::Size(begin as u32 + n));
+                  break;
+                },
+                IResult::Done(i2,_)                  => {
+                  let separated = checked_offset!(input, i2, ret);
+                  match $submac!(i2, $($args2)*) {
+                    IResult::Error(_)                    => {
+                      ret = if $trailing {
+                        IResult::Done(i2, res)
+                      } else {
+                        IResult::Done(&input[begin..], res)
+                      };
+                      break;
+                    },
+                    IResult::Incomplete(Needed::Unknown) => {
+                      ret = IResult::Incomplete(Needed::Unknown);
+                      break;
+                    },
+                    IResult::Incomplete(Needed::Size(n)) => {
+                      ret = IResult::Incomplete(Needed::Size(separated as u32 + n));
+                      break;
+                    },
+                    IResult::Done(i3,o3)                 => {
+                      let offset = checked_offset!(input, i3, ret);
+                      if offset == begin {
+                        // the separator and the element consumed nothing, we would loop forever
+                        ret = IResult::Error($crate::Err::Code(0));
+                        break;
+                      }
+                      res.push(o3);
+                      begin = offset;
+                    }
+                  }
+                }
+              }
+            }
           }
         }
       }
+      ret
     }
   );
 );
@@ -643,16 +2488,35 @@ macro_rules! fold1_impl(
 ///  let a = b"abcdefgh";
 ///
 ///  assert_eq!(take5(a), Done(b"fgh", b"abcde"));
+///
+///  // the count can be computed at runtime in the expression form
+///  let len = 3;
+///  assert_eq!(take!(a, len), Done(b"defgh", b"abc"));
 /// ```
 #[macro_export]
 macro_rules! take(
-  ($name:ident $count:expr) => (
-    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]>{
-      if i.len() < $count {
-        Incomplete(Needed::Size($count))
+  ($i:expr, $count:expr) => (
+    {
+      let input = $i;
+      let cnt   = $count as usize;
+      if input.len() < cnt {
+        IResult::Incomplete(Needed::Size(cnt as u32))
       } else {
-        Done(&i[$count..],&i[0..$count])
+        IResult::Done(&input[cnt..], &input[0..cnt])
+      }
+    }
+  );
+
+  ($name:ident $count:expr) => (
+    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(i:&[u8]) -> IResult<&[u8], &[u8]> {
+        if i.len() < $count {
+          Incomplete(Needed::Size($count))
+        } else {
+          Done(&i[$count..],&i[0..$count])
+        }
       }
+      trace_parser!(stringify!($name), i, call!(i, inner))
     }
   )
 );
@@ -660,28 +2524,31 @@ macro_rules! take(
 #[macro_export]
 macro_rules! take_until(
   ($name:ident $inp:expr) => (
-    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]>{
-      #[inline(always)]
-      fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
-        b.as_bytes()
-      }
+    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(i:&[u8]) -> IResult<&[u8], &[u8]> {
+        #[inline(always)]
+        fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+          b.as_bytes()
+        }
 
-      let expected = $inp;
-      let bytes = as_bytes(&expected);
+        let expected = $inp;
+        let bytes = as_bytes(&expected);
 
-      for idx in 0..i.len() {
-        if idx + bytes.len() > i.len() {
-          return Incomplete(Needed::Size((idx + bytes.len()) as u32))
-        }
-        if &i[idx..idx + bytes.len()] == bytes {
+        for idx in 0..i.len() {
           if idx + bytes.len() > i.len() {
-            return Done(b"", &i[0..idx])
-          } else {
-            return Done(&i[(idx + bytes.len())..], &i[0..idx])
+            return Incomplete(Needed::Size((idx + bytes.len()) as u32))
+          }
+          if &i[idx..idx + bytes.len()] == bytes {
+            if idx + bytes.len() > i.len() {
+              return Done(&i[i.len()..], &i[0..idx])
+            } else {
+              return Done(&i[(idx + bytes.len())..], &i[0..idx])
+            }
           }
         }
+        return Error($crate::Err::Code(0))
       }
-      return Error(0)
+      trace_parser!(stringify!($name), i, call!(i, inner))
     }
   )
 );
@@ -689,24 +2556,27 @@ macro_rules! take_until(
 #[macro_export]
 macro_rules! take_until_and_leave(
   ($name:ident $inp:expr) => (
-    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]>{
-      #[inline(always)]
-      fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
-        b.as_bytes()
-      }
+    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(i:&[u8]) -> IResult<&[u8], &[u8]> {
+        #[inline(always)]
+        fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+          b.as_bytes()
+        }
 
-      let expected = $inp;
-      let bytes = as_bytes(&expected);
+        let expected = $inp;
+        let bytes = as_bytes(&expected);
 
-      for idx in 0..i.len() {
-        if idx + bytes.len() > i.len() {
-          return Incomplete(Needed::Size((idx + bytes.len()) as u32))
-        }
-        if &i[idx..idx+bytes.len()] == bytes {
-          return Done(&i[idx..], &i[0..idx])
+        for idx in 0..i.len() {
+          if idx + bytes.len() > i.len() {
+            return Incomplete(Needed::Size((idx + bytes.len()) as u32))
+          }
+          if &i[idx..idx+bytes.len()] == bytes {
+            return Done(&i[idx..], &i[0..idx])
+          }
         }
+        return Error($crate::Err::Code(0))
       }
-      return Error(0)
+      trace_parser!(stringify!($name), i, call!(i, inner))
     }
   )
 );
@@ -714,30 +2584,33 @@ macro_rules! take_until_and_leave(
 #[macro_export]
 macro_rules! take_until_either(
   ($name:ident $inp:expr) => (
-    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]>{
-      #[inline(always)]
-      fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
-        b.as_bytes()
-      }
+    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(i:&[u8]) -> IResult<&[u8], &[u8]> {
+        #[inline(always)]
+        fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+          b.as_bytes()
+        }
 
-      let expected = $inp;
-      let bytes = as_bytes(&expected);
+        let expected = $inp;
+        let bytes = as_bytes(&expected);
 
-      for idx in 0..i.len() {
-        if idx + 1 > i.len() {
-          return Incomplete(Needed::Size(1 + idx as u32))
-        }
-        for &t in bytes.iter() {
-          if i[idx] == t {
-            if idx + 1 > i.len() {
-              return Done(b"", &i[0..idx])
-            } else {
-              return Done(&i[(idx+1)..], &i[0..idx])
+        for idx in 0..i.len() {
+          if idx + 1 > i.len() {
+            return Incomplete(Needed::Size(1 + idx as u32))
+          }
+          for &t in bytes.iter() {
+            if i[idx] == t {
+              if idx + 1 > i.len() {
+                return Done(&i[i.len()..], &i[0..idx])
+              } else {
+                return Done(&i[(idx+1)..], &i[0..idx])
+              }
             }
           }
         }
+        return Error($crate::Err::Code(0))
       }
-      return Error(0)
+      trace_parser!(stringify!($name), i, call!(i, inner))
     }
   )
 );
@@ -745,119 +2618,392 @@ macro_rules! take_until_either(
 #[macro_export]
 macro_rules! take_until_either_and_leave(
   ($name:ident $inp:expr) => (
-    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]>{
+    fn $name(i:&[u8]) -> IResult<&[u8], &[u8]> {
+      fn inner(i:&[u8]) -> IResult<&[u8], &[u8]> {
+        #[inline(always)]
+        fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+          b.as_bytes()
+        }
+
+        let expected = $inp;
+        let bytes = as_bytes(&expected);
+
+        for idx in 0..i.len() {
+          if idx + 1 > i.len() {
+            return Incomplete(Needed::Size(1 + idx as u32))
+          }
+          for &t in bytes.iter() {
+            if i[idx] == t {
+              return Done(&i[idx..], &i[0..idx])
+            }
+          }
+        }
+        return Error($crate::Err::Code(0))
+      }
+      trace_parser!(stringify!($name), i, call!(i, inner))
+    }
+  )
+);
+
+/// takes bytes until one of the needles is found, and skips it
+///
+/// returns the index of the needle that was found, and the bytes before it. The needles
+/// can have different sizes, and the earliest one in the input wins. If several needles
+/// start at the same position, the first one in the list is chosen.
+///
+/// If the end of the input could be the beginning of a needle, `Incomplete` is returned
+/// with the size needed to check it, even if a needle after it in the list matches: with
+/// `"\r\n", "\r"`, a chunk ending with `\r` is Incomplete. The parser does not keep
+/// state between chunks, a consumer can use `NeedleSearch` to avoid checking the same
+/// data again
+///
+/// ```ignore
+///  take_until_any!(part<&[u8], (usize, &[u8])>, "\r\n\r\n", "--boundary", "\n\n");
+///
+///  assert_eq!(part(b"abc\n\ndef"), Done(b"def", (2, b"abc")));
+///  assert_eq!(part(b"abc--boundarydef"), Done(b"def", (1, b"abc")));
+///  assert_eq!(part(b"abc\r\n"), Incomplete(Needed::Size(7)));
+/// ```
+#[macro_export]
+macro_rules! take_until_any(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, take_until_any!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $($needle:expr),+) => (
+    {
       #[inline(always)]
       fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
         b.as_bytes()
       }
 
-      let expected = $inp;
-      let bytes = as_bytes(&expected);
+      let input = $i;
+      match [$(as_bytes(&$needle)),+] {
+        needles => match $crate::util::find_any(input, &needles) {
+          Ok((pos, idx)) => IResult::Done(&input[(pos + needles[idx].len())..], (idx, &input[..pos])),
+          Err(n)         => IResult::Incomplete(n)
+        }
+      }
+    }
+  );
+);
+
+/// takes bytes until one of the needles is found, and leaves it in the remaining input
+///
+/// works like `take_until_any!`, and returns the index of the needle that was found
+///
+/// ```ignore
+///  take_until_any_and_leave!(part<&[u8], (usize, &[u8])>, "\r\n\r\n", "\n\n");
+///
+///  assert_eq!(part(b"abc\n\ndef"), Done(b"\n\ndef", (1, b"abc")));
+/// ```
+#[macro_export]
+macro_rules! take_until_any_and_leave(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, take_until_any_and_leave!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $($needle:expr),+) => (
+    {
+      #[inline(always)]
+      fn as_bytes<T: $crate::util::AsBytes>(b: &T) -> &[u8] {
+        b.as_bytes()
+      }
+
+      let input = $i;
+      match [$(as_bytes(&$needle)),+] {
+        needles => match $crate::util::find_any(input, &needles) {
+          Ok((pos, idx)) => IResult::Done(&input[pos..], (idx, &input[..pos])),
+          Err(n)         => IResult::Incomplete(n)
+        }
+      }
+    }
+  );
+);
+
+/// parses a size with the first parser, and returns a slice of that size
+///
+/// the size parser can return any integer type. The optional adjustment is added to the
+/// parsed size, for formats where the size includes the header:
+///
+/// ```ignore
+///  // an mp4 box: a be_u32 size, counting its own 4 bytes, then the content
+///  length_bytes!(mp4_box<&[u8], &[u8]>, be_u32, -4);
+///
+///  assert_eq!(mp4_box(b"\x00\x00\x00\x06abcd"), Done(b"cd", b"ab"));
+///  assert_eq!(mp4_box(b"\x00\x00\x00\x0aabcd"), Incomplete(Needed::Size(10)));
+/// ```
+///
+/// if the adjusted size is negative, an error is returned
+#[macro_export]
+macro_rules! length_bytes(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, length_bytes!(input, $($rest)*))
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* ), $adjustment:expr) => (
+    {
+      let input = $i;
+      match $submac!(input, $($args)*) {
+        IResult::Error(e)      => IResult::Error(e),
+        IResult::Incomplete(n) => IResult::Incomplete(n),
+        IResult::Done(i1,size) => {
+          let header = $crate::util::Offset::offset(input, i1);
+          let length = size as i64 + $adjustment as i64;
+          if length < 0 {
+            IResult::Error($crate::Err::Code(0))
+          } else if i1.len() < length as usize {
+            IResult::Incomplete(Needed::Size((header + length as usize) as u32))
+          } else {
+            IResult::Done(&i1[length as usize..], &i1[..length as usize])
+          }
+        }
+      }
+    }
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* )) => (
+    length_bytes!($i, $submac!($($args)*), 0)
+  );
+
+  ($i:expr, $f:expr, $adjustment:expr) => (
+    length_bytes!($i, call!($f), $adjustment)
+  );
+
+  ($i:expr, $f:expr) => (
+    length_bytes!($i, call!($f), 0)
+  );
+);
+
+/// parses a size, then applies the second parser on a slice of that size
+///
+/// the second parser cannot read past the slice, and must consume all of it,
+/// otherwise an error is returned. The slice is complete, so if the second parser
+/// returns Incomplete, length_value_bytes! returns an error
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  length_value_bytes!(sized_x<&[u8], &[u8]>, be_u8, x);
+///
+///  assert_eq!(sized_x(b"\x04abcdef"), Done(b"ef", b"abcd"));
+///  assert_eq!(sized_x(b"\x05abcdef"), Error(Code(0)));
+///  assert_eq!(sized_x(b"\x04ab"), Incomplete(Needed::Size(5)));
+/// ```
+#[macro_export]
+macro_rules! length_value_bytes(
+  ($name:ident<$i:ty,$o:ty>, $($rest:tt)*) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      trace_parser!(stringify!($name), input, length_value_bytes!(input, $($rest)*))
+    }
+  );
 
-      for idx in 0..i.len() {
-        if idx + 1 > i.len() {
-          return Incomplete(Needed::Size(1 + idx as u32))
-        }
-        for &t in bytes.iter() {
-          if i[idx] == t {
-            return Done(&i[idx..], &i[0..idx])
+  ($i:expr, $sizemac:ident!( $($sargs:tt)* ), $submac:ident!( $($args:tt)* )) => (
+    {
+      let input = $i;
+      match length_bytes!(input, $sizemac!($($sargs)*)) {
+        IResult::Error(e)       => IResult::Error(e),
+        IResult::Incomplete(n)  => IResult::Incomplete(n),
+        IResult::Done(i1,bytes) => {
+          match $submac!(bytes, $($args)*) {
+            IResult::Done(rest,o) => {
+              if rest.len() == 0 {
+                IResult::Done(i1, o)
+              } else {
+                IResult::Error($crate::Err::Code(0))
+              }
+            },
+            IResult::Error(e)      => IResult::Error(e.relocate(input, bytes)),
+            IResult::Incomplete(_) => IResult::Error($crate::Err::Code(0))
           }
         }
       }
-      return Error(0)
     }
-  )
+  );
+
+  ($i:expr, $sizemac:ident!( $($sargs:tt)* ), $g:expr) => (
+    length_value_bytes!($i, $sizemac!($($sargs)*), call!($g))
+  );
+
+  ($i:expr, $f:expr, $submac:ident!( $($args:tt)* )) => (
+    length_value_bytes!($i, call!($f), $submac!($($args)*))
+  );
+
+  ($i:expr, $f:expr, $g:expr) => (
+    length_value_bytes!($i, call!($f), call!($g))
+  );
 );
 
-/// returns a result without consuming the input
-///
-/// the embedded parser may return Incomplete
+/// parses a number of elements with the first parser, then applies the second parser
+/// that many times
 ///
 /// ```ignore
-///  tag!(x "abcd");
-///  peek!(ptag<&[u8], &[u8]> x);
-///  let r = ptag(b"abcdefgh"));
-///  assert_eq!(r, Done(b"abcdefgh", b"abcd"));
+///  length_value!(shorts<&[u8], u16> be_u8 be_u16);
+///
+///  assert_eq!(shorts(b"\x02\x00\x01\x00\x02"), Done(b"", vec![1, 2]));
+///  assert_eq!(shorts(b"\x02\x00\x01\x00"), Incomplete(Needed::Size(5)));
 /// ```
 #[macro_export]
 macro_rules! length_value(
   ($name:ident<$i:ty,$o:ty> $f:ident $g:ident) => (
     fn $name(input:$i) -> IResult<$i, Vec<$o>> {
-      match $f(input) {
-        Error(a)      => Error(a),
-        Incomplete(i) => Incomplete(i),
-        Done(i1,nb)   => {
-          let length_token     = input.len() - i1.len();
-          let mut begin        = 0;
-          let mut remaining    = i1.len();
-          let mut res: Vec<$o> = Vec::new();
-
-          loop {
-            if res.len() == nb as usize {
-              return Done(&i1[begin..], res);
-            }
+      fn inner(input:$i) -> IResult<$i, Vec<$o>> {
+        match $f(input) {
+          Error(a)      => Error(a),
+          Incomplete(i) => Incomplete(i),
+          Done(i1,nb)   => {
+            let length_token     = $crate::util::Offset::offset(input, i1);
+            let mut begin        = 0;
+            let mut res: Vec<$o> = Vec::new();
+
+            loop {
+              if res.len() == nb as usize {
+                return Done(&i1[begin..], res);
+              }
 
-            match $g(&i1[begin..]) {
-              Done(i2,o2) => {
-              res.push(o2);
-                let parsed  = remaining - i2.len();
-                begin      += parsed;
-                remaining   = i2.len();
-                if begin   >= i1.len() {
-                  return Incomplete(Needed::Size((length_token + nb as usize * parsed) as u32));
+              match $g(&i1[begin..]) {
+                Done(i2,o2) => {
+                  res.push(o2);
+                  let parsed  = checked_offset!(&i1[begin..], i2);
+                  begin       = begin + parsed;
+                  if res.len() == nb as usize {
+                    return Done(&i1[begin..], res);
+                  }
+                  if begin   >= i1.len() {
+                    return Incomplete(Needed::Size((length_token + nb as usize * parsed) as u32));
+                  }
+                },
+                Error(a)      => return Error(a.relocate(input, &i1[begin..])),
+                Incomplete(Needed::Unknown) => {
+                  return Incomplete(Needed::Unknown)
+                },
+                Incomplete(Needed::Size(a)) => {
+                  return Incomplete(Needed::Size(length_token  as u32 + a * nb as u32))
                 }
-              },
-              Error(a)      => return Error(a),
-              Incomplete(Needed::Unknown) => {
-                return Incomplete(Needed::Unknown)
-              },
-              Incomplete(Needed::Size(a)) => {
-                return Incomplete(Needed::Size(length_token  as u32 + a * nb as u32))
               }
             }
           }
         }
       }
+      trace_parser!(stringify!($name), input, call!(input, inner))
     }
   );
 
   ($name:ident<$i:ty,$o:ty> $f:ident $g:ident $length:expr) => (
     fn $name(input:$i) -> IResult<$i, Vec<$o>> {
-      match $f(input) {
-        Error(a)      => Error(a),
-        Incomplete(i) => Incomplete(i),
-        Done(i1,nb)   => {
-          let length_token     = input.len() - i1.len();
-          let mut begin        = 0;
-          let mut remaining    = i1.len();
-          let mut res: Vec<$o> = Vec::new();
-
-          loop {
-            if res.len() == nb as usize {
-              return Done(&i1[begin..], res);
-            }
+      fn inner(input:$i) -> IResult<$i, Vec<$o>> {
+        match $f(input) {
+          Error(a)      => Error(a),
+          Incomplete(i) => Incomplete(i),
+          Done(i1,nb)   => {
+            let length_token     = $crate::util::Offset::offset(input, i1);
+            let mut begin        = 0;
+            let mut res: Vec<$o> = Vec::new();
+
+            loop {
+              if res.len() == nb as usize {
+                return Done(&i1[begin..], res);
+              }
 
-            match $g(&i1[begin..]) {
-              Done(i2,o2) => {
-                res.push(o2);
-                let parsed  = remaining - i2.len();
-                begin      += parsed;
-                remaining   = i2.len();
-                if begin   >= i1.len() {
-                  return Incomplete(Needed::Size((length_token + nb as usize * $length) as u32));
+              match $g(&i1[begin..]) {
+                Done(i2,o2) => {
+                  res.push(o2);
+                  let parsed  = checked_offset!(&i1[begin..], i2);
+                  begin       = begin + parsed;
+                  if res.len() == nb as usize {
+                    return Done(&i1[begin..], res);
+                  }
+                  if begin   >= i1.len() {
+                    return Incomplete(Needed::Size((length_token + nb as usize * $length) as u32));
+                  }
+                },
+                Error(a)      => return Error(a.relocate(input, &i1[begin..])),
+                Incomplete(Needed::Unknown) => {
+                  return Incomplete(Needed::Unknown)
+                },
+                Incomplete(Needed::Size(a)) => {
+                  return Incomplete(Needed::Size(length_token  as u32 + $length * nb as u32))
                 }
-              },
-              Error(a)      => return Error(a),
-              Incomplete(Needed::Unknown) => {
-                return Incomplete(Needed::Unknown)
-              },
-              Incomplete(Needed::Size(a)) => {
-                return Incomplete(Needed::Size(length_token  as u32 + $length * nb as u32))
               }
             }
           }
         }
       }
+      trace_parser!(stringify!($name), input, call!(input, inner))
+    }
+  );
+);
+
+/// applies a parser and writes its name, result and a hex dump of its input to the debug sink,
+/// if it fails or returns Incomplete
+///
+/// the sink is stderr by default, and can be replaced with `set_debug_sink`. The named
+/// form writes its own name, the inline form writes the parser it applies
+///
+/// ```ignore
+///  tag!(x "abcd");
+///  dbg_dmp!(dx<&[u8], &[u8]>, x);
+///
+///  dx(b"efgh");
+///  // writes:
+///  // dx: Error(Code(0)) on input:
+///  // 00000000	65 66 67 68 	efgh
+/// ```
+#[macro_export]
+macro_rules! dbg_dmp(
+  ($name:ident<$i:ty,$o:ty>, $submac:ident!( $($args:tt)* )) => (
+    fn $name(input:$i) -> IResult<$i,$o> {
+      let res = trace_parser!(stringify!($name), input, $submac!(input, $($args)*));
+      $crate::debug::dump(stringify!($name), input, &res);
+      res
+    }
+  );
+
+  ($name:ident<$i:ty,$o:ty>, $f:expr) => (
+    dbg_dmp!($name<$i,$o>, call!($f));
+  );
+
+  ($i:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      let input = $i;
+      let res   = $submac!(input, $($args)*);
+      $crate::debug::dump(stringify!($submac!($($args)*)), input, &res);
+      res
+    }
+  );
+
+  ($i:expr, $f:expr) => (
+    {
+      let input = $i;
+      let res   = $f(input);
+      $crate::debug::dump(stringify!($f), input, &res);
+      res
+    }
+  );
+);
+
+/// instruments a named parser
+///
+/// the parsers generated by the macros with a name use it. With the `trace` feature,
+/// it logs the entry and exit of the parser, and with the `profile` feature, it updates
+/// the parser's counters, keyed by the module path, the name and the line of the parser.
+/// Without those features, it only applies the parser
+#[macro_export]
+macro_rules! trace_parser(
+  ($name:expr, $i:expr, $submac:ident!( $($args:tt)* )) => (
+    {
+      $crate::debug::trace_enter($name, &$i);
+      let key   = concat!(module_path!(), "::", $name, ":", line!());
+      let start = $crate::profile::profile_start(key);
+      let res   = $submac!($($args)*);
+      $crate::profile::profile_end(key, &$i, start, &res);
+      $crate::debug::trace_exit($name, &res);
+      res
     }
   );
 );
@@ -866,9 +3012,10 @@ macro_rules! length_value(
 mod tests {
   use super::*;
   use map::*;
-  use internal::Needed;
+  use internal::{ConversionError,Needed};
   use internal::IResult;
   use internal::IResult::*;
+  use internal::Err::*;
 
   #[test]
   fn is_a() {
@@ -972,19 +3119,100 @@ mod tests {
     let r2 = f(b"abcdWXYZ");
     assert_eq!(r2, Done(b"WXYZ", C{a: 1, b: None}));
 
+    // the needed size counts from the beginning of the chain
     let r3 = f(b"abcdX");
-    assert_eq!(r3, Incomplete(Needed::Size(4)));
+    assert_eq!(r3, Incomplete(Needed::Size(8)));
+  }
+
+  #[test]
+  fn tuple() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    tuple!(t<&[u8], (&[u8], &[u8], &[u8])>, x, y, x);
+
+    assert_eq!(t(b"abcdefghabcdX"), Done(b"X", (b"abcd", b"efgh", b"abcd")));
+    assert_eq!(t(b"abcdabcdefgh"), Error(Code(0)));
+    assert_eq!(t(b"abcdefghab"), Incomplete(Needed::Size(12)));
+
+    // single element tuple
+    tuple!(t1<&[u8], (&[u8],)>, x);
+    assert_eq!(t1(b"abcdX"), Done(b"X", (b"abcd",)));
+  }
+
+  #[test]
+  fn pair() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    pair!(p<&[u8], (&[u8], &[u8])>, x, y);
+
+    assert_eq!(p(b"abcdefghX"), Done(b"X", (b"abcd", b"efgh")));
+    assert_eq!(p(b"abcdX"), Incomplete(Needed::Size(8)));
+    assert_eq!(p(b"efghabcdX"), Error(Code(0)));
+  }
+
+  #[test]
+  fn preceded_terminated() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    preceded!(p<&[u8], &[u8]>, x, y);
+    terminated!(t<&[u8], &[u8]>, x, y);
+
+    assert_eq!(p(b"abcdefghX"), Done(b"X", b"efgh"));
+    assert_eq!(t(b"abcdefghX"), Done(b"X", b"abcd"));
+    assert_eq!(p(b"abcdabcdX"), Error(Code(0)));
+    assert_eq!(t(b"abcdef"), Incomplete(Needed::Size(8)));
+  }
+
+  #[test]
+  fn delimited() {
+    tag!(lsb "[");
+    tag!(rsb "]");
+    tag!(x "abcd");
+    delimited!(d<&[u8], &[u8]>, lsb, x, rsb);
+
+    assert_eq!(d(b"[abcd]X"), Done(b"X", b"abcd"));
+    assert_eq!(d(b"[abcd"), Incomplete(Needed::Size(6)));
+    assert_eq!(d(b"(abcd)"), Error(Code(0)));
+  }
+
+  #[test]
+  fn separated_pair() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    tag!(equal "=");
+    separated_pair!(s<&[u8], (&[u8], &[u8])>, x, equal, y);
+
+    assert_eq!(s(b"abcd=efghX"), Done(b"X", (b"abcd", b"efgh")));
+    assert_eq!(s(b"abcd:efghX"), Error(Code(0)));
+  }
+
+  #[test]
+  fn sequence_inline() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    tag!(comma ",");
+    fn ret_int(i:&[u8]) -> IResult<&[u8], u8> { Done(i,1) };
+
+    // sub-parsers can be functions, closures or macro calls
+    let r = pair!(&b"abcd,abcd,efgh"[..], separated_list!(comma, x), preceded!(call!(comma), y));
+    assert_eq!(r, Done(&b""[..], (vec![&b"abcd"[..], &b"abcd"[..]], &b"efgh"[..])));
+
+    let r2 = tuple!(&b"abcdef"[..], x, |i| { ret_int(i) }, pair!(y, ret_int));
+    assert_eq!(r2, Incomplete(Needed::Size(8)));
+
+    let r3 = tuple!(&b"abcdefghefgh"[..], x, |i| { ret_int(i) }, pair!(y, ret_int), y);
+    assert_eq!(r3, Done(&b""[..], (&b"abcd"[..], 1, (&b"efgh"[..], 1), &b"efgh"[..])));
   }
 
   #[test]
   fn alt() {
     fn work(input: &[u8]) -> IResult<&[u8],&[u8]> {
-      Done(b"", input)
+      Done(&input[input.len()..], input)
     }
 
     #[allow(unused_variables)]
     fn dont_work(input: &[u8]) -> IResult<&[u8],&[u8]> {
-      Error(3)
+      Error(Code(3))
     }
 
     fn work2(input: &[u8]) -> IResult<&[u8],&[u8]> {
@@ -996,11 +3224,302 @@ mod tests {
     alt!(alt3<&[u8],&[u8]>, dont_work | dont_work | work2 | dont_work);
 
     let a = b"abcd";
-    assert_eq!(alt1(a), Error(1));
+    assert_eq!(alt1(a), Error(Alt(vec![Code(3), Code(3)])));
     assert_eq!(alt2(a), Done(b"", a));
     assert_eq!(alt3(a), Done(a, b""));
   }
 
+  #[test]
+  fn alt_incomplete() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    tag!(z "efghijkl");
+    alt!(alt1<&[u8],&[u8]>, x | y);
+    alt!(alt2<&[u8],&[u8]>, z | y);
+
+    // the first branch already fails, the second one needs more data
+    assert_eq!(alt1(b"ef"), Incomplete(Needed::Size(4)));
+    // the first branch needs more data, the second one is not tried
+    assert_eq!(alt2(b"efghij"), Incomplete(Needed::Size(8)));
+    assert_eq!(alt2(b"efghijkl"), Done(b"", b"efghijkl"));
+  }
+
+  #[test]
+  fn alt_inline() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    tag!(comma ",");
+
+    let r1 = alt!(&b"efgh,abcd"[..], x | terminated!(y, comma));
+    assert_eq!(r1, Done(&b"abcd"[..], &b"efgh"[..]));
+
+    // nested alternatives keep the errors of every branch
+    let r2 = alt!(&b"ijkl"[..], x | alt!(y | call!(comma)));
+    assert_eq!(r2, Error(Alt(vec![Code(0), Alt(vec![Code(0), Code(0)])])));
+  }
+
+  #[test]
+  fn switch() {
+    take!(box_type 4);
+    fn ret_zero(i:&[u8]) -> IResult<&[u8], u32> { Done(i, 0) };
+    switch!(box_body<&[u8], u32>, box_type,
+      b"ftyp" => call!(be_u32)
+    | b"free" => call!(ret_zero)
+    );
+
+    let a = b"ftyp\x00\x00\x00\x01abcd";
+    let b = b"free\x00\x00\x00\x01abcd";
+    let c = b"mdat\x00\x00\x00\x01abcd";
+    let d = b"ftyp\x00\x00";
+    assert_eq!(box_body(a), Done(b"abcd", 1));
+    assert_eq!(box_body(b), Done(b"\x00\x00\x00\x01abcd", 0));
+    assert_eq!(box_body(c), Error(Switch));
+    assert_eq!(box_body(d), Incomplete(Needed::Size(8)));
+
+    // the default arm
+    let r = switch!(&c[..], call!(box_type),
+      b"ftyp" => call!(be_u32)
+    | _       => call!(ret_zero)
+    );
+    assert_eq!(r, Done(&c[4..], 0));
+
+    // the chosen arm failing does not fall through
+    fn fail(_:&[u8]) -> IResult<&[u8], u32> { Error(Code(1)) };
+    let r2 = switch!(&c[..], call!(box_type),
+      b"mdat" => call!(fail)
+    | _       => call!(ret_zero)
+    );
+    assert_eq!(r2, Error(Code(1)));
+    // the errors of the chosen arm are located after the selector
+    let r4 = switch!(&c[..], call!(box_type),
+      b"mdat" => locate!(call!(fail))
+    );
+    assert_eq!(r4, Error(Position(4, Box::new(Code(1)))));
+    let r3 = switch!(&b"mdat\x00"[..], call!(box_type),
+      b"mdat" => call!(be_u32)
+    | _       => call!(ret_zero)
+    );
+    assert_eq!(r3, Incomplete(Needed::Size(8)));
+  }
+
+  #[test]
+  fn chain_dependent() {
+    chain!(data<&[u8], (&[u8], Option<u8>)>,
+      len:   be_u8               ~
+      bytes: take!(len as usize) ~
+      extra: cond!(len > 2, be_u8),
+      ||{(bytes, extra)}
+    );
+
+    assert_eq!(data(b"\x02abX"), Done(&b"X"[..], (&b"ab"[..], None)));
+    assert_eq!(data(b"\x03abcX"), Done(&b""[..], (&b"abc"[..], Some(88))));
+    // the needed sizes count from the beginning of the chain
+    assert_eq!(data(b"\x03abc"), Incomplete(Needed::Size(5)));
+    assert_eq!(data(b"\x04ab"), Incomplete(Needed::Size(5)));
+
+    tag!(x "abcd");
+    let r = chain!(&b"abcd\x01efgh"[..],
+      x                       ~
+      n:   be_u8              ~
+      opt: cond!(n == 0, x)   ~
+      t:   take!(n as usize + 1) ,
+      ||{(opt, t)}
+    );
+    assert_eq!(r, Done(&b"gh"[..], (None, &b"ef"[..])));
+  }
+
+  #[test]
+  fn peek_inline() {
+    tag!(x "abcd");
+    let a = b"abcdefgh";
+    assert_eq!(peek!(&a[..], x), Done(&a[..], &b"abcd"[..]));
+    assert_eq!(peek!(&b"abcdab"[..], pair!(x, x)), Incomplete(Needed::Size(8)));
+    assert_eq!(peek!(&b"efgh"[..], x), Error(Code(0)));
+    assert_eq!(peek!(&b"ab"[..], x), Incomplete(Needed::Size(4)));
+  }
+
+  #[test]
+  fn not() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    terminated!(alone<&[u8], &[u8]>, x, not!(y));
+
+    assert_eq!(alone(b"abcdabcd"), Done(&b"abcd"[..], &b"abcd"[..]));
+    assert_eq!(alone(b"abcdefgh"), Error(Code(0)));
+    assert_eq!(alone(b"abcdef"), Incomplete(Needed::Size(8)));
+    assert_eq!(not!(&b"efgh"[..], x), Done(&b"efgh"[..], ()));
+    assert_eq!(not!(&b"ab"[..], x), Incomplete(Needed::Size(4)));
+  }
+
+  #[test]
+  fn eof() {
+    tag!(x "abcd");
+    terminated!(whole<&[u8], &[u8]>, x, eof!(true));
+
+    assert_eq!(whole(b"abcd"), Done(&b""[..], &b"abcd"[..]));
+    assert_eq!(whole(b"abcdef"), Error(Code(0)));
+    assert_eq!(whole(b"ab"), Incomplete(Needed::Size(4)));
+    assert_eq!(eof!(&b""[..], true), Done(&b""[..], &b""[..]));
+    assert_eq!(eof!("a", true), Error(Code(0)));
+
+    // without the end of the data, an empty input could be followed by more
+    terminated!(partial<&[u8], &[u8]>, x, eof!());
+    assert_eq!(partial(b"abcd"), Incomplete(Needed::Size(5)));
+    assert_eq!(partial(b"abcdef"), Error(Code(0)));
+    assert_eq!(eof!(&b""[..]), Incomplete(Needed::Size(1)));
+
+    eof!(end<&[u8], &[u8]>, true);
+    eof!(more<&[u8], &[u8]>);
+    assert_eq!(end(&b""[..]), Done(&b""[..], &b""[..]));
+    assert_eq!(end(&b"a"[..]), Error(Code(0)));
+    assert_eq!(more(&b""[..]), Incomplete(Needed::Size(1)));
+
+    let r = chain!(&b"abcd"[..], a: x ~ eof!(true), ||{a});
+    assert_eq!(r, Done(&b""[..], &b"abcd"[..]));
+  }
+
+  #[test]
+  fn map() {
+    use nom::{alpha,digit};
+    use std::str;
+
+    map!(len<&[u8], usize>, alpha, |s:&[u8]| s.len());
+    assert_eq!(len(b"abcd123"), Done(&b"123"[..], 4));
+    assert_eq!(len(b"abcd"), Incomplete(Needed::Size(5)));
+
+    map_res!(number<&[u8], u32>, map_res!(digit, str::from_utf8), str::FromStr::from_str);
+    assert_eq!(number(b"123;"), Done(&b";"[..], 123));
+    // the conversion error is kept
+    let overflow = "99999999999".parse::<u32>().unwrap_err();
+    assert_eq!(number(b"99999999999;"), Error(Conversion(ConversionError::new(overflow))));
+
+    map_opt!(first<&[u8], u8>, alpha, |s:&[u8]| s.first().cloned());
+    assert_eq!(first(b"abcd123"), Done(&b"123"[..], b'a'));
+    assert_eq!(first(b"123"), Error(Code(0)));
+
+    tag!(x "abcd");
+    let r = chain!(&b"abcd42abcd;"[..],
+         x                                                             ~
+      n: map_res!(map_res!(digit, str::from_utf8), str::FromStr::from_str) ~
+      l: map!(x, |o:&[u8]| o.len()),
+      ||{(n, l)}
+    );
+    assert_eq!(r, Done(&b";"[..], (42u8, 4)));
+  }
+
+  #[test]
+  fn verify() {
+    verify!(box_size<&[u8], u32>, be_u32, |s:&u32| *s >= 8);
+    assert_eq!(box_size(b"\x00\x00\x00\x10X"), Done(&b"X"[..], 16));
+    assert_eq!(box_size(b"\x00\x00\x00\x04X"), Error(Verify));
+    // the parser's own errors are kept
+    tag!(abcd "abcd");
+    assert_eq!(verify!(&b"efgh"[..], abcd, |_:&&[u8]| true), Error(Code(0)));
+    assert_eq!(box_size(b"\x00\x00"), Incomplete(Needed::Size(4)));
+
+    let r = chain!(&b"\x01\x00\x00\x00\x00\x00\x00\x00\x09"[..],
+      version: verify!(be_u8, |v:&u8| *v == 0 || *v == 1) ~
+      date:    cond!(version == 1, be_u64),
+      ||{date}
+    );
+    assert_eq!(r, Done(&b""[..], Some(9)));
+    let r2 = chain!(&b"\x02\x00"[..],
+      version: verify!(be_u8, |v:&u8| *v == 0 || *v == 1) ~
+      date:    cond!(version == 1, be_u64),
+      ||{date}
+    );
+    assert_eq!(r2, Error(Verify));
+  }
+
+  #[test]
+  fn value() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    alt!(number<&[u8], u8>, value!(x, 1) | value!(y, 2) | value!(0));
+
+    assert_eq!(number(b"abcdX"), Done(&b"X"[..], 1));
+    assert_eq!(number(b"efghX"), Done(&b"X"[..], 2));
+    assert_eq!(number(b"ijklX"), Done(&b"ijklX"[..], 0));
+    assert_eq!(number(b"ef"), Incomplete(Needed::Size(4)));
+
+    let r = chain!(&b"abcdefgh"[..], a: value!(x, 'a') ~ b: value!(y, 'b')?, ||{(a, b)});
+    assert_eq!(r, Done(&b""[..], ('a', Some('b'))));
+  }
+
+  #[test]
+  fn recognize() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    many0!(multi<&[u8], &[u8]> x);
+    recognize!(raw<&[u8], &[u8]>, multi);
+
+    assert_eq!(raw(b"abcdabcdefgh"), Done(&b"efgh"[..], &b"abcdabcd"[..]));
+    assert_eq!(raw(b"efgh"), Done(&b"efgh"[..], &b""[..]));
+    assert_eq!(raw(b"abcdab"), Incomplete(Needed::Size(8)));
+
+    let a = b"abcdefghX";
+    assert_eq!(recognize!(&a[..], chain!(x ~ y?, ||{()})), Done(&b"X"[..], &b"abcdefgh"[..]));
+    assert_eq!(recognize!(&a[..], alt!(y | x)), Done(&b"efghX"[..], &b"abcd"[..]));
+    assert_eq!(recognize!(&b"efgh"[..], x), Error(Code(0)));
+  }
+
+  #[test]
+  fn locate() {
+    tag!(x "abcd");
+    tag!(y "efgh");
+    pair!(p<&[u8], (&[u8], &[u8])>, x, locate!(y));
+
+    assert_eq!(p(b"abcdabcd"), Error(Position(4, Box::new(Code(0)))));
+    // errors that were not located keep no position
+    assert_eq!(p(b"efghefgh"), Error(Code(0)));
+
+    // the positions add up through the nested sequences
+    chain!(c<&[u8], Vec<(&[u8], &[u8])> >, x ~ v: count!(p, 2), ||{v});
+    assert_eq!(c(b"abcdabcdefghabcdabcd"), Error(Position(16, Box::new(Code(0)))));
+  }
+
+  #[test]
+  fn cond() {
+    tag!(x "abcd");
+    let a = b"abcdef";
+    let b = b"efgh";
+
+    assert_eq!(cond!(&a[..], true, x), Done(&b"ef"[..], Some(&b"abcd"[..])));
+    assert_eq!(cond!(&a[..], false, x), Done(&a[..], None));
+    assert_eq!(cond!(&b[..], true, x), Error(Code(0)));
+    assert_eq!(cond!(&b[..], false, call!(x)), Done(&b[..], None));
+    assert_eq!(cond!(&b"ab"[..], true, x), Incomplete(Needed::Size(4)));
+  }
+
+  #[test]
+  fn permutation() {
+    tag!(a "abcd");
+    tag!(b "efgh");
+    tag!(c "ijkl");
+    permutation!(perm<&[u8], (&[u8], Option<&[u8]>, &[u8])>, a, b?, c);
+
+    assert_eq!(perm(b"abcdefghijklX"), Done(&b"X"[..], (&b"abcd"[..], Some(&b"efgh"[..]), &b"ijkl"[..])));
+    assert_eq!(perm(b"ijklefghabcdX"), Done(&b"X"[..], (&b"abcd"[..], Some(&b"efgh"[..]), &b"ijkl"[..])));
+    assert_eq!(perm(b"ijklabcdXXXX"), Done(&b"XXXX"[..], (&b"abcd"[..], None, &b"ijkl"[..])));
+    assert_eq!(perm(b"ijklefghXXXX"), Error(Missing(0)));
+    assert_eq!(perm(b"ijklefghijkl"), Error(Duplicate(2)));
+    assert_eq!(perm(b"ijklab"), Incomplete(Needed::Size(8)));
+    // the members not found yet could follow at the end of the input
+    assert_eq!(perm(b"efgh"), Incomplete(Needed::Size(8)));
+    assert_eq!(perm(b"ijklabcd"), Incomplete(Needed::Size(12)));
+
+    // on complete data, they are missing
+    permutation!(perm_complete<&[u8], (&[u8], Option<&[u8]>, &[u8])>, complete!(a), complete!(b)?, complete!(c));
+    let input = &b"ijklabcd"[..];
+    assert_eq!(perm_complete(input), Done(&input[8..], (&b"abcd"[..], None, &b"ijkl"[..])));
+    assert_eq!(perm_complete(b"efgh"), Error(Missing(0)));
+
+    // inline, with macros as members
+    let r = permutation!(&b"efghabcdX"[..], a, value!(b, 2)?, call!(c_opt));
+    fn c_opt(i:&[u8]) -> IResult<&[u8], &[u8]> { Done(i, &i[..0]) }
+    assert_eq!(r, Done(&b"X"[..], (&b"abcd"[..], Some(2), &b""[..])));
+  }
+
   #[test]
   fn opt() {
     tag!(x "abcd");
@@ -1021,7 +3540,7 @@ mod tests {
     assert_eq!(r1, Done(b"abcdefgh", b"abcd"));
 
     let r1 = ptag(b"efgh");
-    assert_eq!(r1, Error(0));
+    assert_eq!(r1, Error(Code(0)));
   }
 
   #[test]
@@ -1033,11 +3552,38 @@ mod tests {
     let b = b"abcdabcdef";
     let c = b"azerty";
 
+    // tag! needs 4 bytes to reject "ef", the list could continue
+    assert_eq!(multi(a), Incomplete(Needed::Size(8)));
+    assert_eq!(multi(b), Incomplete(Needed::Size(12)));
+    assert_eq!(multi(c), Done(b"azerty", Vec::new()));
+
     let res1 = vec![b"abcd"];
-    assert_eq!(multi(a), Done(b"ef", res1));
+    assert_eq!(multi(b"abcdefgh"), Done(b"efgh", res1));
     let res2 = vec![b"abcd", b"abcd"];
-    assert_eq!(multi(b), Done(b"ef", res2));
-    assert_eq!(multi(c), Done(b"azerty", Vec::new()));
+    assert_eq!(multi(b"abcdabcdefgh"), Done(b"efgh", res2));
+
+    // an item cut at the end of the input
+    let d = b"abcdabcdab";
+    assert_eq!(multi(d), Incomplete(Needed::Size(12)));
+    // the input ends on an item boundary, another item could follow
+    assert_eq!(multi(b"abcdabcd"), Incomplete(Needed::Size(12)));
+    assert_eq!(multi(b""), Incomplete(Needed::Size(4)));
+
+    complete!(cx<&[u8],&[u8]>, x);
+    many0!(multi_complete<&[u8],&[u8]> cx);
+    let input = &b"abcdabcd"[..];
+    assert_eq!(multi_complete(input), Done(&input[8..], vec![&b"abcd"[..], &b"abcd"[..]]));
+    assert_eq!(cx(b"ab"), Error(UnexpectedEnd(Needed::Size(4))));
+
+    tag!(empty "");
+    many0!(multi_empty<&[u8],&[u8]> empty);
+    assert_eq!(multi_empty(a), Error(Code(0)));
+
+    // the remaining input must be a part of the input
+    fn foreign(i:&[u8]) -> IResult<&[u8],&[u8]> { Done(b"", &i[..1]) }
+    many0!(multi_foreign<&[u8],&[u8]> foreign);
+    assert_eq!(multi_foreign(a), Error(ForeignInput));
+    assert_eq!(recognize!(&a[..], foreign), Error(ForeignInput));
   }
 
   #[test]
@@ -1048,11 +3594,218 @@ mod tests {
     let a = b"abcdef";
     let b = b"abcdabcdef";
     let c = b"azerty";
+    // tag! needs 4 bytes to reject "ef", the list could continue
+    assert_eq!(multi(a), Incomplete(Needed::Size(8)));
+    assert_eq!(multi(b), Incomplete(Needed::Size(12)));
+    assert_eq!(multi(c), Error(Code(0)));
+
+    let res1 = vec![b"abcd"];
+    assert_eq!(multi(b"abcdefgh"), Done(b"efgh", res1));
+    let res2 = vec![b"abcd", b"abcd"];
+    assert_eq!(multi(b"abcdabcdefgh"), Done(b"efgh", res2));
+
+    let d = b"abcdabcdab";
+    assert_eq!(multi(d), Incomplete(Needed::Size(12)));
+    assert_eq!(multi(b"abcdabcd"), Incomplete(Needed::Size(12)));
+    let e = b"ab";
+    assert_eq!(multi(e), Incomplete(Needed::Size(4)));
+  }
+
+  #[test]
+  fn fold0() {
+    tag!(x "abcd");
+    fn count_x(input:&[u8], z:usize) -> IResult<&[u8], usize> {
+      fold0_impl!(<&[u8], usize>, |acc, _| { acc + 1 }, x, input, z);
+    }
+
+    assert_eq!(count_x(b"abcdabcdefgh", 0), Done(b"efgh", 2));
+    assert_eq!(count_x(b"abcdabcdab", 0), Incomplete(Needed::Size(12)));
+    assert_eq!(count_x(b"abcdabcd", 0), Incomplete(Needed::Size(12)));
+
+    fold0!(count_named<&[u8], usize>, |acc, _| { acc + 1 }, x);
+    assert_eq!(count_named(b"abcdabcdefgh", 0), Done(b"efgh", 2));
+    assert_eq!(count_named(b"efgh", 0), Done(b"efgh", 0));
+  }
+
+  #[test]
+  fn fold1() {
+    tag!(x "abcd");
+    fold1!(count_x<&[u8], usize>, |acc, _| { acc + 1 }, x);
+
+    assert_eq!(count_x(b"abcdabcdefgh", 0), Done(b"efgh", 2));
+    assert_eq!(count_x(b"efgh", 0), Error(Code(0)));
+    assert_eq!(count_x(b"abcdab", 0), Incomplete(Needed::Size(8)));
+  }
+
+  #[test]
+  fn separated_list() {
+    tag!(comma ",");
+    tag!(abcd "abcd");
+    tag!(empty "");
+    separated_list!(multi<&[u8],&[u8]>, comma, abcd);
+    separated_list!(multi_empty<&[u8],&[u8]>, empty, empty);
+
+    let a = b"abcdefgh";
+    let b = b"abcd,abcdefgh";
+    let c = b"azerty";
+    let d = b"abcd,abcd,efgh";
+    let e = b"abcd,ab";
+
+    let res1 = vec![b"abcd"];
+    assert_eq!(multi(a), Done(b"efgh", res1));
+    let res2 = vec![b"abcd", b"abcd"];
+    assert_eq!(multi(b), Done(b"efgh", res2));
+    assert_eq!(multi(c), Done(b"azerty", Vec::new()));
+    let res3 = vec![b"abcd", b"abcd"];
+    assert_eq!(multi(d), Done(b",efgh", res3));
+    assert_eq!(multi(e), Incomplete(Needed::Size(9)));
+    assert_eq!(multi_empty(a), Error(Code(0)));
+
+    // the input is split after an element, then after a separator
+    assert_eq!(multi(b"abcd,abcd"), Incomplete(Needed::Size(10)));
+    assert_eq!(multi(b"abcd,"), Incomplete(Needed::Size(9)));
+
+    separated_list!(multi_complete<&[u8],&[u8]>, complete!(comma), complete!(abcd));
+    let input = &b"abcd,abcd"[..];
+    assert_eq!(multi_complete(input), Done(&input[9..], vec![&b"abcd"[..], &b"abcd"[..]]));
+    assert_eq!(multi_complete(b"abcd,"), Done(&b","[..], vec![&b"abcd"[..]]));
+  }
+
+  #[test]
+  fn separated_nonempty_list() {
+    tag!(comma ",");
+    tag!(abcd "abcd");
+    separated_nonempty_list!(multi<&[u8],&[u8]>, comma, abcd);
+
+    let a = b"abcdefgh";
+    let b = b"abcd,abcdefgh";
+    let c = b"azerty";
+
     let res1 = vec![b"abcd"];
-    assert_eq!(multi(a), Done(b"ef", res1));
+    assert_eq!(multi(a), Done(b"efgh", res1));
     let res2 = vec![b"abcd", b"abcd"];
-    assert_eq!(multi(b), Done(b"ef", res2));
-    assert_eq!(multi(c), Error(0));
+    assert_eq!(multi(b), Done(b"efgh", res2));
+    assert_eq!(multi(c), Error(Code(0)));
+  }
+
+  #[test]
+  fn separated_list_trailing() {
+    tag!(comma ",");
+    tag!(abcd "abcd");
+    separated_list_trailing!(multi<&[u8],&[u8]>, comma, abcd);
+
+    let a = b"abcd,abcd,efgh";
+    let b = b"abcd,abcd,";
+    let c = b"abcd,abcdefgh";
+
+    let res = vec![b"abcd", b"abcd"];
+    assert_eq!(multi(a), Done(b"efgh", res));
+    // another element could follow the trailing separator
+    assert_eq!(multi(b), Incomplete(Needed::Size(14)));
+    let res3 = vec![b"abcd", b"abcd"];
+    assert_eq!(multi(c), Done(b"efgh", res3));
+
+    separated_list_trailing!(multi_complete<&[u8],&[u8]>, complete!(comma), complete!(abcd));
+    assert_eq!(multi_complete(&b[..]), Done(&b[10..], vec![&b"abcd"[..], &b"abcd"[..]]));
+  }
+
+  #[test]
+  fn count() {
+    tag!(x "abcd");
+    count!(cnt<&[u8],&[u8]>, x, 2);
+
+    let a = b"abcdabcdabcdefgh";
+    let b = b"abcdefgh";
+    let c = b"abcdab";
+
+    let res = vec![b"abcd", b"abcd"];
+    assert_eq!(cnt(a), Done(b"abcdefgh", res));
+    assert_eq!(cnt(b), Error(Code(0)));
+    assert_eq!(cnt(c), Incomplete(Needed::Size(8)));
+
+    // the count can be computed from previously parsed data
+    let d = vec![3, 0, 1, 0, 2, 0, 3, 4];
+    let r = match be_u8(&d[..]) {
+      Done(i, nb) => count!(i, be_u16, nb as usize),
+      _           => Error(Code(0))
+    };
+    let res2: Vec<u16> = vec![1, 2, 3];
+    assert_eq!(r, Done(&d[7..], res2));
+
+    fn nothing(i:&[u8]) -> IResult<&[u8], u8> { Done(i,1) };
+    count!(cnt_nothing<&[u8],u8>, nothing, 2);
+    assert_eq!(cnt_nothing(a), Error(Code(0)));
+  }
+
+  #[test]
+  fn count_fixed() {
+    count_fixed!(three<&[u8],u16>, be_u16, 3);
+
+    let a = vec![0, 1, 0, 2, 0, 3, 4];
+    let b = vec![0, 1, 0, 2, 0];
+    assert_eq!(three(&a[..]), Done(&a[6..], [1, 2, 3]));
+    assert_eq!(three(&b[..]), Incomplete(Needed::Size(6)));
+
+    let r = count_fixed!(&a[..], u8, be_u8, 2);
+    assert_eq!(r, Done(&a[2..], [0, 1]));
+
+    fn nothing(i:&[u8]) -> IResult<&[u8], u8> { Done(i,1) };
+    count_fixed!(three_nothing<&[u8],u8>, nothing, 3);
+    assert_eq!(three_nothing(&a[..]), Error(Code(0)));
+  }
+
+  #[test]
+  fn many_m_n() {
+    tag!(x "abcd");
+    many_m_n!(multi<&[u8],&[u8]>, 2, 3, x);
+
+    let a = b"abcdefgh";
+    let b = b"abcdabcdefgh";
+    let c = b"abcdabcdabcdabcdefgh";
+    let d = b"abcdabcdab";
+
+    assert_eq!(multi(a), Error(Code(0)));
+    let res1 = vec![b"abcd", b"abcd"];
+    assert_eq!(multi(b), Done(b"efgh", res1));
+    let res2 = vec![b"abcd", b"abcd", b"abcd"];
+    assert_eq!(multi(c), Done(b"abcdefgh", res2));
+    assert_eq!(multi(d), Incomplete(Needed::Size(12)));
+    // a third item could follow at the end of the input
+    assert_eq!(multi(b"abcdabcd"), Incomplete(Needed::Size(12)));
+    let e = &b"abcdabcdabcd"[..];
+    assert_eq!(multi(e), Done(&e[12..], vec![&b"abcd"[..], &b"abcd"[..], &b"abcd"[..]]));
+
+    // the bounds are evaluated once, and must be ordered
+    let mut evaluations = 0;
+    let r = many_m_n!(&b"abcdefgh"[..], { evaluations += 1; 1 }, 2, x);
+    assert_eq!(r, Done(&b"efgh"[..], vec![&b"abcd"[..]]));
+    assert_eq!(evaluations, 1);
+    assert_eq!(many_m_n!(&b"abcdabcdabcd"[..], 3, 2, x), Error(Code(0)));
+
+    fn nothing(i:&[u8]) -> IResult<&[u8], u8> { Done(i,1) };
+    many_m_n!(multi_nothing<&[u8],u8>, 1, 100, nothing);
+    assert_eq!(multi_nothing(a), Error(Code(0)));
+  }
+
+  #[test]
+  fn many_till() {
+    tag!(x "abcd");
+    tag!(end "end");
+    many_till!(multi<&[u8],&[u8],&[u8]>, x, end);
+
+    let a = b"abcdabcdendefgh";
+    let b = b"abcdefgh";
+    let c = b"abcdab";
+
+    let res = (vec![b"abcd", b"abcd"], b"end");
+    assert_eq!(multi(a), Done(b"efgh", res));
+    assert_eq!(multi(b), Error(Code(0)));
+    // the terminator is checked first
+    assert_eq!(multi(c), Incomplete(Needed::Size(7)));
+
+    fn nothing(i:&[u8]) -> IResult<&[u8], u8> { Done(i,1) };
+    many_till!(multi_nothing<&[u8],u8,&[u8]>, nothing, end);
+    assert_eq!(multi_nothing(b), Error(Code(0)));
   }
 
   #[test]
@@ -1071,7 +3824,94 @@ mod tests {
     assert_eq!(r3, Incomplete(Needed::Size(7)));
   }
 
-  use nom::{be_u8,be_u16};
+  use nom::{be_u8,be_u16,be_u32,be_u64};
+  #[test]
+  fn take_while() {
+    take_while!(lower<&[u8], &[u8]>, |c| c >= b'a' && c <= b'z');
+    assert_eq!(lower(&b"abcD"[..]), Done(&b"D"[..], &b"abc"[..]));
+    assert_eq!(lower(&b"ABC"[..]), Done(&b"ABC"[..], &b""[..]));
+    assert_eq!(lower(&b"abc"[..]), Incomplete(Needed::Size(4)));
+
+    take_while1!(lower1<&[u8], &[u8]>, |c| c >= b'a' && c <= b'z');
+    assert_eq!(lower1(&b"abcD"[..]), Done(&b"D"[..], &b"abc"[..]));
+    assert_eq!(lower1(&b"ABC"[..]), Error(Code(0)));
+    assert_eq!(lower1(&b""[..]), Incomplete(Needed::Size(1)));
+
+    take_while_m_n!(hex<&[u8], &[u8]>, 2, 4, |c| (c as char).is_digit(16));
+    assert_eq!(hex(&b"1f;"[..]), Done(&b";"[..], &b"1f"[..]));
+    assert_eq!(hex(&b"12345"[..]), Done(&b"5"[..], &b"1234"[..]));
+    assert_eq!(hex(&b"1234"[..]), Done(&b""[..], &b"1234"[..]));
+    assert_eq!(hex(&b"1;"[..]), Error(Code(0)));
+    assert_eq!(hex(&b"12"[..]), Incomplete(Needed::Size(3)));
+
+    let sep = b';';
+    let r = take_till!(&b"ab;cd"[..], |c| c == sep);
+    assert_eq!(r, Done(&b";cd"[..], &b"ab"[..]));
+    let r = take_till!(&b"abcd"[..], |c| c == sep);
+    assert_eq!(r, Incomplete(Needed::Size(5)));
+  }
+
+  #[test]
+  fn take_until_any() {
+    take_until_any!(part<&[u8], (usize, &[u8])>, "\r\n\r\n", "--boundary", "\n\n");
+    assert_eq!(part(&b"abc\n\ndef"[..]), Done(&b"def"[..], (2, &b"abc"[..])));
+    assert_eq!(part(&b"abc--boundary\n\n"[..]), Done(&b"\n\n"[..], (1, &b"abc"[..])));
+    assert_eq!(part(&b"\r\n\r\n"[..]), Done(&b""[..], (0, &b""[..])));
+    // the needles starting at the end of the input need more data
+    assert_eq!(part(&b"abc\r\n"[..]), Incomplete(Needed::Size(7)));
+    assert_eq!(part(&b"abc--bou"[..]), Incomplete(Needed::Size(13)));
+    assert_eq!(part(&b"abc\n"[..]), Incomplete(Needed::Size(5)));
+    assert_eq!(part(&b"abc"[..]), Incomplete(Needed::Size(5)));
+
+    // at the same position, the first needle in the list wins
+    take_until_any!(line<&[u8], (usize, &[u8])>, "\r\n", "\r");
+    assert_eq!(line(&b"ab\r\ncd"[..]), Done(&b"cd"[..], (0, &b"ab"[..])));
+    assert_eq!(line(&b"ab\rcd"[..]), Done(&b"cd"[..], (1, &b"ab"[..])));
+    assert_eq!(line(&b"ab\r"[..]), Incomplete(Needed::Size(4)));
+    take_until_any!(word<&[u8], (usize, &[u8])>, "ab", "abcx");
+    assert_eq!(word(&b"zzab"[..]), Done(&b""[..], (0, &b"zz"[..])));
+    assert_eq!(word(&b"zzabc"[..]), Done(&b"c"[..], (0, &b"zz"[..])));
+
+    let boundary = "--sep";
+    let r = take_until_any_and_leave!(&b"abc--sepdef"[..], boundary, "\n\n");
+    assert_eq!(r, Done(&b"--sepdef"[..], (0, &b"abc"[..])));
+  }
+
+  #[test]
+  fn length_bytes() {
+    length_bytes!(byte_sized<&[u8], &[u8]>, be_u8);
+    length_bytes!(box_sized<&[u8], &[u8]>, be_u32, -4);
+
+    assert_eq!(byte_sized(&b"\x03abcde"[..]), Done(&b"de"[..], &b"abc"[..]));
+    assert_eq!(byte_sized(&b"\x00abc"[..]), Done(&b"abc"[..], &b""[..]));
+    assert_eq!(byte_sized(&b"\x05abc"[..]), Incomplete(Needed::Size(6)));
+    assert_eq!(byte_sized(&b""[..]), Incomplete(Needed::Size(1)));
+
+    assert_eq!(box_sized(&b"\x00\x00\x00\x06abcd"[..]), Done(&b"cd"[..], &b"ab"[..]));
+    assert_eq!(box_sized(&b"\x00\x00\x00\x0aabcd"[..]), Incomplete(Needed::Size(10)));
+    assert_eq!(box_sized(&b"\x00\x00\x00\x02abcd"[..]), Error(Code(0)));
+
+    let r = length_bytes!(&b"\x00\x02abc"[..], be_u16);
+    assert_eq!(r, Done(&b"c"[..], &b"ab"[..]));
+  }
+
+  #[test]
+  fn length_value_bytes() {
+    tag!(x "abcd");
+    length_value_bytes!(sized_x<&[u8], &[u8]>, be_u8, x);
+    // many0! would return Incomplete at the end of the slice, since more items could follow
+    count!(xs<&[u8], &[u8]>, x, 2);
+    length_value_bytes!(sized_xs<&[u8], Vec<&[u8]> >, be_u8, xs);
+
+    assert_eq!(sized_x(&b"\x04abcdef"[..]), Done(&b"ef"[..], &b"abcd"[..]));
+    assert_eq!(sized_x(&b"\x05abcdef"[..]), Error(Code(0)));
+    assert_eq!(sized_x(&b"\x03abcdef"[..]), Error(Code(0)));
+    assert_eq!(sized_x(&b"\x04ab"[..]), Incomplete(Needed::Size(5)));
+
+    assert_eq!(sized_xs(&b"\x08abcdabcdabcd"[..]), Done(&b"abcd"[..], vec![&b"abcd"[..], &b"abcd"[..]]));
+    assert_eq!(sized_xs(&b"\x06abcdabcd"[..]), Error(Code(0)));
+  }
+
   #[test]
   fn length_value_test() {
     length_value!(tst1<&[u8], u16 > be_u8 be_u16);
@@ -1082,6 +3922,7 @@ mod tests {
     let i3 = vec![2, 5, 6, 3];
     let i4 = vec![2, 5, 6, 3, 4, 5, 7];
     let i5 = vec![3, 5, 6, 3, 4, 5];
+    let i6 = vec![2, 5, 6, 3, 4];
 
     let r1: Vec<u16> = Vec::new();
     let r2: Vec<u16> = vec![1286];
@@ -1091,6 +3932,8 @@ mod tests {
     assert_eq!(tst1(&i3), IResult::Incomplete(Needed::Size(5)));
     assert_eq!(tst1(&i4), IResult::Done(&i4[5..], r4));
     assert_eq!(tst1(&i5), IResult::Incomplete(Needed::Size(7)));
+    // the last element ends the input
+    assert_eq!(tst1(&i6), IResult::Done(&i6[5..], vec![1286, 772]));
 
     let r6: Vec<u16> = Vec::new();
     let r7: Vec<u16> = vec![1286];
@@ -1,400 +1,158 @@
 //! Basic combination functions
 //!
-//! Provides the flat_map, map, map_opt and map_res methods used elsewhere.
+//! Provides the flat_map, map, map_opt, map_res, map_err, and_then and unwrap_or methods used elsewhere.
 //!
-//! Traits provided here must be implemented for any new combination of I and O types in IResult<I,O>
+//! Those methods are implemented directly on IResult<I,O>, for any I and O types. The FlatMap,
+//! FlatMapOpt and Functor traits are kept for compatibility, and call those methods
 
+use std::error;
 use internal::*;
 use internal::IResult::*;
-
-/// flat_map is a method of IResult<R,S>, takes a function Fn(S) -> IResult<S,T>,
-/// and returns a IResult<S,T>
-///
-/// ```
-/// use nom::IResult::Done;
-/// use nom::FlatMap;
-/// use std::str;
-/// Done((),()).flat_map(|data| { println!("data: {:?}", data); Done(data,())});
-/// ```
-pub trait FlatMap<I:?Sized,O:?Sized,N:?Sized> {
-  fn flat_map<'y,F:Fn(O) -> IResult<O,N>>(& self, f: F) -> IResult<I,N>;
-}
-/*
-pub fn compose<'x,'y,'z,R,S,T,F:Fn(S) -> IResult<S,T>>(f:F, g:IResultClosure<'z,R,S>) -> IResultClosure<'x,R,T> {
-  Box::new(move |input: R| -> IResult<'x,R,T> {
-    g(input).flat_map(f)
-  })
-}
-*/
-/// derives flat_map implementation for a list of IResult types with referenced types
-/// like str or vectors
-///
-/// ```
-/// //flat_map_ref_impl! { [bool] [uint] };
-/// ```
-#[macro_export]
-macro_rules! flat_map_ref_impl {
-  ($($t:ty)*) => ($(
-      impl<'a,'b,'z,R,T> FlatMap<&'b R,&'a $t, T> for IResult<&'b R,&'a $t> {
-        #[allow(unused_variables)]
-        fn flat_map<'y,F:Fn(&'a $t) -> IResult<&'a $t,T>>(&self, f: F) -> IResult<&'b R,T> {
-          match self {
-            &Error(ref e) => Error(*e),
-            &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-            &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(|input:&'b R| { cl(input).map(f) }),
-            &Done(ref i, ref o) => match f(*o) {
-              Error(ref e) => Error(*e),
-              Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-              Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-              Done(_, o2) => Done(*i, o2)
-            }
-          }
-        }
-      }
-      impl<'a,'z,T> FlatMap<(),&'a $t, T> for IResult<(),&'a $t> {
-        #[allow(unused_variables)]
-        fn flat_map<'y,F:Fn(&'a $t) -> IResult<&'a $t,T>>(&self, f: F) -> IResult<(),T> {
-          match self {
-            &Error(ref e) => Error(*e),
-            &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-            &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)), //Incomplete(|input:I| { cl(input).map(f) })
-            &Done((), ref o) => match f(*o) {
-              Error(ref e) => Error(*e),
-              Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-              Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-              Done(_, o2) => Done((), o2)
-            }
-          }
-        }
-      }
-  )*)
-}
-
-flat_map_ref_impl! {
-  str [bool] [char] [usize] [u8] [u16] [u32] [u64] [isize] [i8] [i16] [i32] [i64] [f32] [f64]
-}
-
-
-impl<'a,'b,'z, T> FlatMap<&'b [u8],&'a [u8], T> for IResult<&'b [u8],&'a [u8]> {
-  #[allow(unused_variables)]
-  fn flat_map<'y,F:Fn(&'a [u8]) -> IResult<&'a [u8],T>>(&self, f: F) -> IResult<&'b [u8],T> {
+use internal::Err::*;
+
+impl<I,O> IResult<I,O> {
+  /// applies a function to the output of a `Done`, and keeps the same remaining input
+  ///
+  /// ```
+  /// use nom::IResult::Done;
+  /// use std::str;
+  /// let res = Done((), b"abcd").map(|data| { str::from_utf8(data).unwrap() });
+  /// assert_eq!(res, Done((), "abcd"));
+  /// ```
+  pub fn map<N, F: FnOnce(O) -> N>(self, f: F) -> IResult<I,N> {
     match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(|input:&'b R| { cl(input).map(f) }),
-      &Done(ref i, ref o) => match f(*o) {
-        Error(ref e) => Error(*e),
-        Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-        Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-        Done(_, o2) => Done(*i, o2)
-      }
+      Error(e)      => Error(e),
+      Incomplete(n) => Incomplete(n),
+      Done(i, o)    => Done(i, f(o))
     }
   }
-}
-/// derives flat_map implementation for a list of specific IResult types
-///
-/// ```
-/// //flat_map_impl! { bool uint };
-/// ```
-#[macro_export]
-macro_rules! flat_map_impl {
-  ($($t:ty)*) => ($(
-      impl<'a,'z,R,T> FlatMap<&'a R,$t, T> for IResult<&'a R,$t> {
-        #[allow(unused_variables)]
-        fn flat_map<'y,F:Fn($t) -> IResult<$t,T>>(&self, f: F) -> IResult<&'a R,T> {
-          match self {
-            &Error(ref e) => Error(*e),
-            &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-            &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-            &Done(ref i, o) => match f(o) {
-              Error(ref e) => Error(*e),
-              Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-              Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-              Done(i2, o2) => Done(*i, o2)
-            }
-          }
-        }
-      }
-      impl<'z,T> FlatMap<(),$t, T> for IResult<(),$t> {
-        #[allow(unused_variables)]
-        fn flat_map<'y,F:Fn($t) -> IResult<$t,T>>(&self, f: F) -> IResult<(),T> {
-          match self {
-            &Error(ref e) => Error(*e),
-            &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-            &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-            &Done((), o) => match f(o) {
-              Error(ref e) => Error(*e),
-              Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-              Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-              Done(i2, o2) => Done((), o2)
-            }
-          }
-        }
-      }
-  )*)
-}
-
-flat_map_impl! {
-  bool char usize u8 u16 u32 u64 isize i8 i16 i32 i64 f32 f64
-}
-
 
-impl<'a,'z,R,T> FlatMap<&'a R,(), T> for IResult<&'a R,()> {
-  #[allow(unused_variables)]
-  fn flat_map<'y,F: Fn(()) -> IResult<(),T>>(&self, f: F) -> IResult<&'a R,T> {
+  /// applies a function returning an Option to the output of a `Done`,
+  /// returns `Error(Code(0))` if the function returns None
+  pub fn map_opt<N, F: FnOnce(O) -> Option<N>>(self, f: F) -> IResult<I,N> {
     match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Incomplete(f), //Incomplete(|input:I| { cl(input).map(f) })
-      //&Incomplete(ref cl) => Incomplete(Box::new(move |input| { cl(input).flat_map(f) })),
-      &Done(ref i, ()) => match f(()) {
-        Error(ref e) => Error(*e),
-        Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-        Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-        Done(_, o2) => Done(*i, o2)
+      Error(e)      => Error(e),
+      Incomplete(n) => Incomplete(n),
+      Done(i, o)    => match f(o) {
+        Some(output) => Done(i, output),
+        None         => Error(Code(0))
       }
     }
   }
-}
 
-impl<'a,'x,'z,S,T> FlatMap<(),&'a S,T> for IResult<(),&'a S> {
-  #[allow(unused_variables)]
-  fn flat_map<'y,F:Fn(&'a S) -> IResult<&'a S,T>>(&self, f: F) -> IResult<(),T> {
+  /// applies a function returning a Result to the output of a `Done`,
+  /// the function's error is kept in a `Conversion` error
+  ///
+  /// the error type must implement `std::error::Error`. For a function returning another
+  /// error type, like `()` or `&str`, use `map_opt(|o| f(o).ok())`, or the `FlatMapOpt`
+  /// shim, which accepts any error type
+  ///
+  /// ```
+  /// use nom::IResult::Done;
+  /// use std::str;
+  /// let res = Done((), b"abcd").map_res(|data| { str::from_utf8(data) });
+  /// assert_eq!(res, Done((), "abcd"));
+  /// ```
+  pub fn map_res<N, E: error::Error+Send+Sync+'static, F: FnOnce(O) -> Result<N,E>>(self, f: F) -> IResult<I,N> {
     match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Incomplete(f), //Incomplete(|input:I| { cl(input).map(f) })
-      &Done((), ref o) => match f(*o) {
-        Error(ref e) => Error(*e),
-        Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-        Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-        Done(_, o2) => Done((), o2)
+      Error(e)      => Error(e),
+      Incomplete(n) => Incomplete(n),
+      Done(i, o)    => match f(o) {
+        Ok(output) => Done(i, output),
+        Err(e)     => Error(Conversion(ConversionError::new(e)))
       }
     }
   }
-}
 
-impl<'x,'z,T> FlatMap<(),(),T> for IResult<(),()> {
-  #[allow(unused_variables)]
-  fn flat_map<'y,F:Fn(()) -> IResult<(),T>>(&self, f: F) -> IResult<(),T> {
+  /// applies a parser to the output of a `Done`, and returns its result with the
+  /// remaining input of the first `Done`
+  ///
+  /// ```
+  /// use nom::IResult::Done;
+  /// Done((),()).flat_map(|data| { println!("data: {:?}", data); Done(data,())});
+  /// ```
+  pub fn flat_map<N, F: FnOnce(O) -> IResult<O,N>>(self, f: F) -> IResult<I,N> {
     match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Incomplete(f), //Incomplete(|input:I| { cl(input).map(f) })
-      &Done((), ()) => match f(()) {
-        Error(ref e) => Error(*e),
-        Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-        Incomplete(Needed::Size(ref i2)) => Incomplete(Needed::Size(*i2)),
-        Done(_, o2) => Done((), o2)
+      Error(e)      => Error(e),
+      Incomplete(n) => Incomplete(n),
+      Done(i, o)    => match f(o) {
+        Error(e)      => Error(e),
+        Incomplete(n) => Incomplete(n),
+        Done(_, o2)   => Done(i, o2)
       }
     }
   }
-}
 
-/// map_opt and map_res are used to combine common functions with parsers
-///
-/// ```
-/// use nom::IResult::Done;
-/// use nom::FlatMapOpt;
-/// use std::str;
-/// let res = Done((), b"abcd").map_res(|data| { str::from_utf8(data) });
-/// assert_eq!(res, Done((), "abcd"));
-/// ```
-pub trait FlatMapOpt<I,O,N> {
-  fn map_opt<'y,F: Fn(O) -> Option<N>>(& self, f: F) -> IResult<I,N>;
-  fn map_res<'y,P,F: Fn(O) -> Result<N,P>>(& self, f: F) -> IResult<I,N>;
-}
-
-/// derives map_opt and map_res implementations for a list of IResult types with referenced types
-/// like str or vectors
-#[macro_export]
-macro_rules! map_ref_impl {
-  ($i:ty, $o:ty) => (
-      impl<'a,'b,'z,T> FlatMapOpt<&'b $i,&'a $o, T> for IResult<&'b $i,&'a $o> {
-        #[allow(unused_variables)]
-        fn map_opt<'y,F:Fn(&'a $o) -> Option<T>>(&self, f: F) -> IResult<&'b $i,T> {
-          match self {
-            &Error(ref e) => Error(*e),
-            &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-            &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-            //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-            &Done(ref i, ref o) => match f(*o) {
-              Some(output) => Done(*i, output),
-              None         => Error(0)
-            }
-          }
-        }
-
-        #[allow(unused_variables)]
-        fn map_res<'y,U, F: Fn(&'a $o) -> Result<T,U>>(&self, f: F) -> IResult<&'b $i,T> {
-          match self {
-            &Error(ref e) => Error(*e),
-            &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-            &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-            //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-            &Done(ref i, ref o) => match f(*o) {
-              Ok(output) => Done(*i, output),
-              Err(_)     => Error(0)
-            }
-          }
-        }
-      }
-  )
-}
-
-map_ref_impl!([u8], [u8]);
-map_ref_impl!([u8], str);
-map_ref_impl!(str,  [u8]);
-map_ref_impl!(str,  str);
-
-impl<'a,'z,S,T> FlatMapOpt<(), &'a[S], T> for IResult<(),&'a [S]> {
-  #[allow(unused_variables)]
-  fn map_opt<'y,F:Fn(&'a[S]) -> Option<T>>(&self, f: F) -> IResult<(),T> {
+  /// applies a function to the error of an `Error`
+  pub fn map_err<F: FnOnce(Err) -> Err>(self, f: F) -> IResult<I,O> {
     match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), ref o) => match f(*o) {
-        Some(output) => Done((), output),
-        None         => Error(0)
-      }
-    }
-  }
-
-  #[allow(unused_variables)]
-  fn map_res<'y,U, F: Fn(&'a[S]) -> Result<T,U>>(&self, f: F) -> IResult<(),T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), ref o) => match f(*o) {
-        Ok(output) => Done((), output),
-        Err(_)     => Error(0)
-      }
+      Error(e)      => Error(f(e)),
+      Incomplete(n) => Incomplete(n),
+      Done(i, o)    => Done(i, o)
     }
   }
-}
 
-impl<'a,'z,T> FlatMapOpt<(),&'a str, T> for IResult<(),&'a str> {
-  #[allow(unused_variables)]
-  fn map_opt<'y,F:Fn(&'a str) -> Option<T>>(&self, f: F) -> IResult<(),T> {
+  /// applies a parser to the remaining input and the output of a `Done`, and returns its result
+  ///
+  /// ```
+  /// use nom::IResult::Done;
+  /// use nom::be_u8;
+  /// let res = Done(&b"\x02ab"[..], 1u8).and_then(|i, o| be_u8(i).map(|o2| o + o2));
+  /// assert_eq!(res, Done(&b"ab"[..], 3));
+  /// ```
+  pub fn and_then<N, F: FnOnce(I, O) -> IResult<I,N>>(self, f: F) -> IResult<I,N> {
     match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), ref o) => match f(*o) {
-        Some(output) => Done((), output),
-        None         => Error(0)
-      }
+      Error(e)      => Error(e),
+      Incomplete(n) => Incomplete(n),
+      Done(i, o)    => f(i, o)
     }
   }
 
-  #[allow(unused_variables)]
-  fn map_res<'y,U, F: Fn(&'a str) -> Result<T,U>>(&self, f: F) -> IResult<(),T> {
+  /// returns the output of a `Done`, or the provided default value
+  pub fn unwrap_or(self, default: O) -> O {
     match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), ref o) => match f(*o) {
-        Ok(output) => Done((), output),
-        Err(_)     => Error(0)
-      }
+      Done(_, o) => o,
+      _          => default
     }
   }
 }
 
-impl<'a,'z,R,T> FlatMapOpt<&'a[R], (), T> for IResult<&'a[R],()> {
-  #[allow(unused_variables)]
-  fn map_opt<'y,F:Fn(()) -> Option<T>>(&self, f: F) -> IResult<&'a[R],T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, __) => match f(()) {
-        Some(output) => Done(*i, output),
-        None         => Error(0)
-      }
-    }
-  }
-
-  #[allow(unused_variables)]
-  fn map_res<'y,U, F: Fn(()) -> Result<T,U>>(&self, f: F) -> IResult<&'a [R],T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, ref o) => match f(*o) {
-        Ok(output) => Done(*i, output),
-        Err(_)     => Error(0)
-      }
-    }
-  }
+/// flat_map is a method of IResult<R,S>, takes a function Fn(S) -> IResult<S,T>,
+/// and returns a IResult<S,T>
+///
+/// kept for compatibility, the `flat_map` method of IResult should be used instead
+pub trait FlatMap<I:?Sized,O:?Sized,N:?Sized> {
+  fn flat_map<F:Fn(O) -> IResult<O,N>>(& self, f: F) -> IResult<I,N>;
 }
 
-impl<'a,'z,T> FlatMapOpt<&'a str, (), T> for IResult<&'a str,()> {
-  #[allow(unused_variables)]
-  fn map_opt<'y,F:Fn(()) -> Option<T>>(&self, f: F) -> IResult<&'a str,T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, __) => match f(()) {
-        Some(output) => Done(*i, output),
-        None         => Error(0)
-      }
-    }
+impl<I:Clone,O:Clone,N> FlatMap<I,O,N> for IResult<I,O> {
+  fn flat_map<F:Fn(O) -> IResult<O,N>>(&self, f: F) -> IResult<I,N> {
+    self.clone().flat_map(f)
   }
+}
 
-  #[allow(unused_variables)]
-  fn map_res<'y,U, F: Fn(()) -> Result<T,U>>(&self, f: F) -> IResult<&'a str,T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),// Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, ref o) => match f(*o) {
-        Ok(output) => Done(*i, output),
-        Err(_)     => Error(0)
-      }
-    }
-  }
+/// map_opt and map_res are used to combine common functions with parsers
+///
+/// kept for compatibility, the `map_opt` and `map_res` methods of IResult should be used instead.
+/// The error type of the function of `map_res` is not bounded, so its error cannot be kept:
+/// it returns a `Conversion` error holding `ConversionError::unknown()`
+pub trait FlatMapOpt<I,O,N> {
+  fn map_opt<F: Fn(O) -> Option<N>>(& self, f: F) -> IResult<I,N>;
+  fn map_res<P,F: Fn(O) -> Result<N,P>>(& self, f: F) -> IResult<I,N>;
 }
 
-impl<'z,T> FlatMapOpt<(),(), T> for IResult<(),()> {
-  #[allow(unused_variables)]
-  fn map_opt<'y,F:Fn(()) -> Option<T>>(&self, f: F) -> IResult<(),T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), __) => match f(()) {
-        Some(output) => Done((), output),
-        None         => Error(0)
-      }
-    }
+impl<I:Clone,O:Clone,N> FlatMapOpt<I,O,N> for IResult<I,O> {
+  fn map_opt<F: Fn(O) -> Option<N>>(&self, f: F) -> IResult<I,N> {
+    self.clone().map_opt(f)
   }
 
-  #[allow(unused_variables)]
-  fn map_res<'y,U, F: Fn(()) -> Result<T,U>>(&self, f: F) -> IResult<(),T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), ref o) => match f(*o) {
-        Ok(output) => Done(*o, output),
-        Err(_)     => Error(0)
+  fn map_res<P,F: Fn(O) -> Result<N,P>>(&self, f: F) -> IResult<I,N> {
+    match self.clone() {
+      Error(e)      => Error(e),
+      Incomplete(n) => Incomplete(n),
+      Done(i, o)    => match f(o) {
+        Ok(output) => Done(i, output),
+        Err(_)     => Error(Conversion(ConversionError::unknown()))
       }
     }
   }
@@ -402,124 +160,23 @@ impl<'z,T> FlatMapOpt<(),(), T> for IResult<(),()> {
 
 /// map applies a parser function directly to the output of another parser function
 ///
-///```
-/// use nom::IResult::Done;
-/// use nom::Functor;
-/// use std::str;
-/// let res = Done((), b"abcd").map(|data| { str::from_utf8(data).unwrap() });
-/// assert_eq!(res, Done((), "abcd"));
-///```
+/// kept for compatibility, the `map` method of IResult should be used instead
 pub trait Functor<I,O,N> {
-  fn map<'y,F: Fn(O) -> N>(& self, f: F) -> IResult<I,N>;
-}
-
-#[macro_export]
-macro_rules! map2_ref_impl {
-  ($i:ty, $o:ty) => (
-      impl<'a,'b,'z,T> Functor<&'b $i,&'a $o, T> for IResult<&'b $i,&'a $o> {
-        #[allow(unused_variables)]
-        fn map<'y,F: Fn(&'a $o) -> T>(&self, f: F) -> IResult<&'b $i,T> {
-          match self {
-            &Error(ref e) => Error(*e),
-            &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-            &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-            //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-            &Done(ref i, ref o) => Done(*i,f(*o))
-          }
-        }
-      }
-  )
-}
-
-map2_ref_impl!([u8], [u8]);
-map2_ref_impl!([u8], str);
-map2_ref_impl!(str,  [u8]);
-map2_ref_impl!(str,  str);
-
-impl<'a,'z,S,T> Functor<(), &'a[S], T> for IResult<(),&'a [S]> {
-  #[allow(unused_variables)]
-  fn map<'y,F: Fn(&'a[S]) -> T>(&self, f: F) -> IResult<(),T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), ref o) => Done((),f(*o))
-    }
-  }
-}
-
-impl<'a,'z,R,T> Functor<&'a R, (), T> for IResult<&'a R,()> {
-  #[allow(unused_variables)]
-  fn map<'y,F: Fn(()) -> T>(&self, f: F) -> IResult<&'a R,T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, ()) => Done(*i,f(()))
-    }
-  }
-}
-
-impl<'a,'z,R,T> Functor<&'a [R], (), T> for IResult<&'a [R],()> {
-  #[allow(unused_variables)]
-  fn map<'y,F: Fn(()) -> T>(&self, f: F) -> IResult<&'a [R],T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, ()) => Done(*i,f(()))
-    }
-  }
-}
-
-impl<'a,'z,T> Functor<&'a str, (), T> for IResult<&'a str,()> {
-  #[allow(unused_variables)]
-  fn map<'y,F: Fn(()) -> T>(&self, f: F) -> IResult<&'a str,T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, ()) => Done(*i,f(()))
-    }
-  }
-}
-
-impl<'a,'z,T> Functor<(), (), T> for IResult<(),()> {
-  #[allow(unused_variables)]
-  fn map<'y,F: Fn(()) -> T>(&self, f: F) -> IResult<(),T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done(ref i, ()) => Done(*i,f(()))
-    }
-  }
+  fn map<F: Fn(O) -> N>(& self, f: F) -> IResult<I,N>;
 }
 
-impl<'a,'z,T> Functor<(), &'a str, T> for IResult<(),&'a str> {
-  #[allow(unused_variables)]
-  fn map<'y,F: Fn(&'a str) -> T>(&self, f: F) -> IResult<(),T> {
-  //fn map<F: Fn(&'a str) -> T>(&self, f: F) -> IResult<(),T> {
-    match self {
-      &Error(ref e) => Error(*e),
-      &Incomplete(Needed::Unknown) => Incomplete(Needed::Unknown),
-      &Incomplete(Needed::Size(ref i)) => Incomplete(Needed::Size(*i)),//Incomplete(*i),
-      //&Incomplete(ref cl) => Error(0),//Incomplete(|input: &'a I| {*cl(input).mapf(f)}),
-      &Done((), ref o) => Done((),f(*o))
-    }
+impl<I:Clone,O:Clone,N> Functor<I,O,N> for IResult<I,O> {
+  fn map<F: Fn(O) -> N>(&self, f: F) -> IResult<I,N> {
+    self.clone().map(f)
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
-  use internal::IResult;
+  use internal::{ConversionError,IResult,Needed};
   use internal::IResult::*;
+  use internal::Err::*;
   use std::str;
   use std::fmt::Debug;
 
@@ -569,6 +226,42 @@ mod tests {
     assert_eq!(res3, Done("abcd", "efgh"));
   }
 
+  #[derive(Debug,PartialEq,Eq,Clone)]
+  struct Point {
+    x: u8,
+    y: u8
+  }
+
+  #[test]
+  fn generic_methods() {
+    let res: IResult<&[u8], Point> = Done(&b"ab"[..], Point{x: 1, y: 2});
+    assert_eq!(res.clone().map(|p| p.x + p.y), Done(&b"ab"[..], 3));
+    assert_eq!(res.clone().map_opt(|p| if p.x > 1 { Some(p.x) } else { None }), Error(Code(0)));
+    let invalid = str::from_utf8(&[1, 0xff]).unwrap_err();
+    assert_eq!(res.clone().map_res(|p| str::from_utf8(&[p.x, 0xff]).map(|s| s.len())),
+      Error(Conversion(ConversionError::new(invalid))));
+    assert_eq!(res.clone().flat_map(|p| Done(p.clone(), p.y)), Done(&b"ab"[..], 2));
+    assert_eq!(res.clone().and_then(|i, p| Done(&i[1..], p.x)), Done(&b"b"[..], 1));
+    assert_eq!(res.clone().unwrap_or(Point{x: 0, y: 0}), Point{x: 1, y: 2});
+    assert_eq!(res.clone().map_err(|_| Code(1)), res);
+
+    let err: IResult<&[u8], Point> = Error(Code(0));
+    assert_eq!(err.clone().map_err(|e| Alt(vec![e])), Error(Alt(vec![Code(0)])));
+    assert_eq!(err.clone().unwrap_or(Point{x: 0, y: 0}), Point{x: 0, y: 0});
+    let inc: IResult<&[u8], Point> = Incomplete(Needed::Size(3));
+    assert_eq!(inc.map(|p| p.x), Incomplete(Needed::Size(3)));
+  }
+
+  #[test]
+  fn compatibility_traits() {
+    let res: IResult<&[u8], &[u8]> = Done(&b"ab"[..], &b"cd"[..]);
+    assert_eq!(Functor::map(&res, |o:&[u8]| o.len()), Done(&b"ab"[..], 2));
+    assert_eq!(FlatMapOpt::map_res(&res, |o| str::from_utf8(o)), Done(&b"ab"[..], "cd"));
+    assert_eq!(FlatMapOpt::map_res(&res, |_| "x".parse::<u8>()), Error(Conversion(ConversionError::unknown())));
+    // any error type is accepted
+    assert_eq!(FlatMapOpt::map_res(&res, |_| -> Result<u8, ()> { Err(()) }), Error(Conversion(ConversionError::unknown())));
+  }
+
   #[test]
   fn t1() {
     let v1:Vec<u8> = vec![1,2,3];
@@ -0,0 +1,264 @@
+//! Packrat memoization
+//!
+//! `Memo` caches the results of parsers for a parse session, indexed by a parser id
+//! and the offset in the input, so backtracking combinators do not apply the same parser
+//! at the same position twice.
+//!
+//! A `Memo` borrows the complete input of the session: the cache lives as long as the
+//! `Memo`, and can be emptied with `clear`. The parsers using it receive it as first
+//! argument, to memoize their own sub-parsers:
+//!
+//! ```ignore
+//!  const TERM: usize = 0;
+//!
+//!  fn term<'a>(m: &mut Memo<'a, i64>, input: &'a [u8]) -> IResult<&'a [u8], i64> {
+//!    m.apply(TERM, input, |m, i| { ... })
+//!  }
+//!
+//!  let mut m = Memo::new(input);
+//!  let res = expr(&mut m, input);
+//!  println!("hit rate: {}", m.stats().hit_rate());
+//! ```
+//!
+//! All the memoized parsers of a `Memo` return the same output type. Parsers returning
+//! other types can use other `Memo` instances on the same input
+
+use std::collections::HashMap;
+use internal::IResult;
+use internal::IResult::*;
+use internal::Err::*;
+use util::Offset;
+
+/// statistics about the use of a `Memo` cache
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub struct MemoStats {
+  /// number of results found in the cache
+  pub hits:    usize,
+  /// number of parser applications
+  pub misses:  usize,
+  /// number of results stored in the cache
+  pub entries: usize
+}
+
+impl MemoStats {
+  /// ratio of the calls that were answered by the cache, between 0 and 1
+  pub fn hit_rate(&self) -> f64 {
+    if self.hits + self.misses == 0 {
+      0.0
+    } else {
+      self.hits as f64 / (self.hits + self.misses) as f64
+    }
+  }
+}
+
+/// memoization cache of the results of parsers, for one input
+pub struct Memo<'a,O> {
+  input:  &'a [u8],
+  cache:  HashMap<(usize, usize), IResult<&'a [u8], O>>,
+  hits:   usize,
+  misses: usize
+}
+
+impl<'a,O:Clone> Memo<'a,O> {
+  /// creates a cache for a parse session on `input`
+  pub fn new(input: &'a [u8]) -> Memo<'a,O> {
+    Memo {
+      input:  input,
+      cache:  HashMap::new(),
+      hits:   0,
+      misses: 0
+    }
+  }
+
+  /// applies the parser identified by `id` to `input`, or returns its cached result
+  ///
+  /// `input` must be a suffix of the input of the session, otherwise a `ForeignInput` error
+  /// is returned. The parser must not call itself at the same position (left recursion),
+  /// `apply_left_rec` handles that case
+  pub fn apply<F>(&mut self, id: usize, input: &'a [u8], parser: F) -> IResult<&'a [u8], O>
+    where F: Fn(&mut Memo<'a,O>, &'a [u8]) -> IResult<&'a [u8], O> {
+    let key = match self.offset(input) {
+      Some(offset) => (id, offset),
+      None         => return Error(ForeignInput)
+    };
+    let cached = self.cache.get(&key).map(|r| r.clone());
+    if let Some(res) = cached {
+      self.hits += 1;
+      return res;
+    }
+
+    self.misses += 1;
+    let res = parser(self, input);
+    self.cache.insert(key, res.clone());
+    res
+  }
+
+  /// applies a left recursive parser, like `expr = expr '-' num | num`
+  ///
+  /// the recursive call at the same position first fails with a `LeftRecursion` error, then
+  /// the parser is applied again with the previous result as the answer of the recursive
+  /// call, as long as it consumes more input (seed growing). If the parser never succeeds,
+  /// its first error is returned
+  pub fn apply_left_rec<F>(&mut self, id: usize, input: &'a [u8], parser: F) -> IResult<&'a [u8], O>
+    where F: Fn(&mut Memo<'a,O>, &'a [u8]) -> IResult<&'a [u8], O> {
+    let key = match self.offset(input) {
+      Some(offset) => (id, offset),
+      None         => return Error(ForeignInput)
+    };
+    let cached = self.cache.get(&key).map(|r| r.clone());
+    if let Some(res) = cached {
+      self.hits += 1;
+      return res;
+    }
+
+    // the seed: the recursive call fails
+    self.cache.insert(key, Error(LeftRecursion));
+    let mut best: IResult<&'a [u8], O> = Error(LeftRecursion);
+    loop {
+      self.misses += 1;
+      let res = parser(self, input);
+      let grows = match (&res, &best) {
+        (&Done(i, _), &Done(j, _)) => i.len() < j.len(),
+        (&Done(_, _), _)           => true,
+        _                          => false
+      };
+      if !grows {
+        // if the parser never succeeded, its error is the result
+        if !best.is_done() {
+          best = res;
+        }
+        break;
+      }
+      best = res;
+      self.cache.insert(key, best.clone());
+    }
+    self.cache.insert(key, best.clone());
+    best
+  }
+
+  /// returns the hit and miss counts, and the size of the cache
+  pub fn stats(&self) -> MemoStats {
+    MemoStats {
+      hits:    self.hits,
+      misses:  self.misses,
+      entries: self.cache.len()
+    }
+  }
+
+  /// empties the cache and resets the statistics
+  pub fn clear(&mut self) {
+    self.cache.clear();
+    self.hits   = 0;
+    self.misses = 0;
+  }
+
+  // offset of a suffix of the input of the session, the key of its results
+  fn offset(&self, input: &'a [u8]) -> Option<usize> {
+    match self.input.checked_offset(input) {
+      Some(offset) if offset + input.len() == self.input.len() => Some(offset),
+      _                                                        => None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use internal::{ConversionError,IResult,Needed};
+  use internal::IResult::*;
+  use internal::Err::*;
+  use nom::digit;
+  use std::str;
+  use std::cell::Cell;
+
+  tag!(minus "-");
+  tag!(x     "x");
+  tag!(y     "y");
+  map_res!(number<&[u8], i64>, map_res!(digit, str::from_utf8), str::FromStr::from_str);
+
+  const NUM:  usize = 0;
+  const EXPR: usize = 1;
+
+  // expr = expr '-' num | num
+  fn expr<'a>(m: &mut Memo<'a,i64>, input: &'a [u8]) -> IResult<&'a [u8], i64> {
+    m.apply_left_rec(EXPR, input, |m, i| {
+      let sub = match expr(m, i) {
+        Done(i2, a) => match minus(i2) {
+          Done(i3, _) => match num(m, i3) {
+            Done(i4, b) => Done(i4, a - b),
+            _           => Error(Code(0))
+          },
+          _           => Error(Code(0))
+        },
+        _           => Error(Code(0))
+      };
+      if sub.is_done() { sub } else { num(m, i) }
+    })
+  }
+
+  fn num<'a>(m: &mut Memo<'a,i64>, input: &'a [u8]) -> IResult<&'a [u8], i64> {
+    m.apply(NUM, input, |_, i| number(i))
+  }
+
+  fn counted_num<'a>(m: &mut Memo<'a,i64>, input: &'a [u8], calls: &Cell<usize>) -> IResult<&'a [u8], i64> {
+    m.apply(NUM, input, |_, i| {
+      calls.set(calls.get() + 1);
+      number(i)
+    })
+  }
+
+  #[test]
+  fn memoize() {
+    let calls = Cell::new(0);
+    let input = &b"123y"[..];
+    let mut m: Memo<i64> = Memo::new(input);
+
+    // s = num 'x' | num 'y'
+    let first  = counted_num(&mut m, input, &calls).and_then(|i, n| x(i).map(|_| n));
+    assert!(first.is_err());
+    let second = counted_num(&mut m, input, &calls).and_then(|i, n| y(i).map(|_| n));
+    assert_eq!(second, Done(&b""[..], 123));
+
+    assert_eq!(calls.get(), 1);
+    assert_eq!(m.stats(), MemoStats { hits: 1, misses: 1, entries: 1 });
+    assert_eq!(m.stats().hit_rate(), 0.5);
+
+    m.clear();
+    assert_eq!(m.stats(), MemoStats { hits: 0, misses: 0, entries: 0 });
+    counted_num(&mut m, input, &calls);
+    assert_eq!(calls.get(), 2);
+  }
+
+  #[test]
+  fn left_recursion() {
+    let input = &b"10-2-3;"[..];
+    let mut m = Memo::new(input);
+    assert_eq!(expr(&mut m, input), Done(&b";"[..], 5));
+
+    let input2 = &b"7;"[..];
+    let mut m2 = Memo::new(input2);
+    assert_eq!(expr(&mut m2, input2), Done(&b";"[..], 7));
+
+    // the error of the first attempt
+    let input3 = &b"-7"[..];
+    let mut m3 = Memo::new(input3);
+    let empty = "".parse::<i64>().unwrap_err();
+    assert_eq!(expr(&mut m3, input3), Error(Conversion(ConversionError::new(empty))));
+    // a parser returning the answer of its recursive call
+    m3.clear();
+    assert_eq!(m3.apply_left_rec(NUM, input3, |m, i| m.apply_left_rec(NUM, i, |_, _| Done(i, 0))),
+      Error(LeftRecursion));
+  }
+
+  #[test]
+  fn foreign_input() {
+    let input = &b"123-4"[..];
+    let copy  = input.to_vec();
+    let mut m = Memo::new(input);
+    assert_eq!(num(&mut m, &input[..3]), Error(ForeignInput));
+    assert_eq!(num(&mut m, &copy[..]), Error(ForeignInput));
+    assert_eq!(expr(&mut m, &copy[..]), Error(ForeignInput));
+    assert_eq!(m.stats().entries, 0);
+    assert_eq!(num(&mut m, input), Done(&b"-4"[..], 123));
+  }
+}
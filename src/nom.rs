@@ -11,6 +11,7 @@ extern crate collections;
 use std::fmt::Debug;
 use internal::*;
 use internal::IResult::*;
+use internal::Err::*;
 use std::mem::transmute;
 
 pub fn tag_cl<'a,'b>(rec:&'a[u8]) ->  Box<Fn(&'b[u8]) -> IResult<&'b[u8], &'b[u8]> + 'a> {
@@ -18,7 +19,7 @@ pub fn tag_cl<'a,'b>(rec:&'a[u8]) ->  Box<Fn(&'b[u8]) -> IResult<&'b[u8], &'b[u8
     if i.len() >= rec.len() && &i[0..rec.len()] == rec {
       Done(&i[rec.len()..], &i[0..rec.len()])
     } else {
-      Error(0)
+      Error(Code(0))
     }
   })
 }
@@ -35,6 +36,8 @@ pub fn begin<'a>(input: &'a [u8]) -> IResult<(), &'a [u8]> {
 // FIXME: when rust-lang/rust#17436 is fixed, macros will be able to export
 // public methods
 //pub is_not!(line_ending b"\r\n")
+//
+// like alpha or digit, the line could continue after the end of the input
 pub fn not_line_ending(input:&[u8]) -> IResult<&[u8], &[u8]> {
   for idx in 0..input.len() {
     for &i in b"\r\n".iter() {
@@ -43,7 +46,7 @@ pub fn not_line_ending(input:&[u8]) -> IResult<&[u8], &[u8]> {
       }
     }
   }
-  Done(b"", input)
+  Incomplete(Needed::Size(input.len() as u32 + 1))
 }
 
 tag!(tag_ln "\n");
@@ -68,53 +71,26 @@ pub fn is_space(chr:u8) -> bool {
   chr == ' ' as u8 || chr == '\t' as u8
 }
 
-// FIXME: when rust-lang/rust#17436 is fixed, macros will be able to export
-//pub filter!(alpha is_alphabetic)
-//pub filter!(digit is_digit)
-//pub filter!(alphanumeric is_alphanumeric)
+// the end of the input could be followed by more matching bytes, so these
+// parsers return Incomplete if they reach it
 pub fn alpha(input:&[u8]) -> IResult<&[u8], &[u8]> {
-  for idx in 0..input.len() {
-    if !is_alphabetic(input[idx]) {
-      return Done(&input[idx..], &input[0..idx])
-    }
-  }
-  Done(b"", input)
+  take_while!(input, is_alphabetic)
 }
 
 pub fn digit(input:&[u8]) -> IResult<&[u8], &[u8]> {
-  for idx in 0..input.len() {
-    if !is_digit(input[idx]) {
-      return Done(&input[idx..], &input[0..idx])
-    }
-  }
-  Done(b"", input)
+  take_while!(input, is_digit)
 }
 
 pub fn alphanumeric(input:&[u8]) -> IResult<&[u8], &[u8]> {
-  for idx in 0..input.len() {
-    if !is_alphanumeric(input[idx]) {
-      return Done(&input[idx..], &input[0..idx])
-    }
-  }
-  Done(b"", input)
+  take_while!(input, is_alphanumeric)
 }
 
 pub fn space(input:&[u8]) -> IResult<&[u8], &[u8]> {
-  for idx in 0..input.len() {
-    if !is_space(input[idx]) {
-      return Done(&input[idx..], &input[0..idx])
-    }
-  }
-  Done(b"", input)
+  take_while!(input, is_space)
 }
 
 pub fn multispace(input:&[u8]) -> IResult<&[u8], &[u8]> {
-  for idx in 0..input.len() {
-    if !is_space(input[idx]) && input[idx] != '\r' as u8 && input[idx] != '\n' as u8 {
-      return Done(&input[idx..], &input[0..idx])
-    }
-  }
-  Done(b"", input)
+  take_while!(input, |c| is_space(c) || c == '\r' as u8 || c == '\n' as u8)
 }
 
 pub fn sized_buffer(input:&[u8]) -> IResult<&[u8], &[u8]> {
@@ -134,7 +110,7 @@ pub fn sized_buffer(input:&[u8]) -> IResult<&[u8], &[u8]> {
 pub fn length_value(input:&[u8]) -> IResult<&[u8], &[u8]> {
   let input_len = input.len();
   if input_len == 0 {
-    return IResult::Error(0)
+    return IResult::Error(Code(0))
   }
 
   let len = input[0] as usize;
@@ -213,6 +189,7 @@ mod tests {
   use internal::Needed;
   use internal::IResult;
   use internal::IResult::*;
+  use internal::Err::*;
 
   #[test]
   fn tag_closure() {
@@ -221,7 +198,7 @@ mod tests {
     assert_eq!(r, Done(b"abcdefgh", b"abcd"));
 
     let r2 = x(b"abcefgh");
-    assert_eq!(r2, Error(0));
+    assert_eq!(r2, Error(Code(0)));
   }
 
   #[test]
@@ -232,19 +209,21 @@ mod tests {
     let c = b"a123";
     let d = "azé12".as_bytes();
     let e = b" ";
-    assert_eq!(alpha(a), Done(empty, a));
+    assert_eq!(alpha(a), Incomplete(Needed::Size(5)));
     assert_eq!(alpha(b), Done(b, empty));
     assert_eq!(alpha(c), Done(&c[1..], b"a"));
     assert_eq!(alpha(d), Done("é12".as_bytes(), b"az"));
     assert_eq!(digit(a), Done(a, empty));
-    assert_eq!(digit(b), Done(empty, b));
+    assert_eq!(digit(b), Incomplete(Needed::Size(5)));
     assert_eq!(digit(c), Done(c, empty));
     assert_eq!(digit(d), Done(d, empty));
-    assert_eq!(alphanumeric(a), Done(empty, a));
-    assert_eq!(alphanumeric(b), Done(empty, b));
-    assert_eq!(alphanumeric(c), Done(empty, c));
+    assert_eq!(alphanumeric(a), Incomplete(Needed::Size(5)));
+    assert_eq!(alphanumeric(b), Incomplete(Needed::Size(5)));
+    assert_eq!(alphanumeric(c), Incomplete(Needed::Size(5)));
     assert_eq!(alphanumeric(d), Done("é12".as_bytes(), b"az"));
-    assert_eq!(space(e), Done(b"", b" "));
+    assert_eq!(space(e), Incomplete(Needed::Size(2)));
+    assert_eq!(space(b" \tx"), Done(&b"x"[..], &b" \t"[..]));
+    assert_eq!(multispace(b" \r\nx"), Done(&b"x"[..], &b" \r\n"[..]));
   }
 
   #[test]
@@ -255,8 +234,9 @@ mod tests {
     let b = b"ab12cd\nefgh\nijkl";
     assert_eq!(not_line_ending(b), Done(b"\nefgh\nijkl", b"ab12cd"));
 
+    // the line could continue
     let c = b"ab12cd";
-    assert_eq!(not_line_ending(c), Done(b"", b"ab12cd"));
+    assert_eq!(not_line_ending(c), Incomplete(Needed::Size(7)));
   }
 
   #[test]
@@ -0,0 +1,253 @@
+//! Operator precedence parsing
+//!
+//! Parses expressions made of atoms, prefix, infix and postfix operators, by precedence climbing.
+//!
+//! Each operator table is a parser returning the operator and its precedence (and the
+//! associativity for infix operators). Those tables are easy to write with `alt!` and `value!`:
+//!
+//! ```ignore
+//!  alt!(infix<&[u8], (Op, u32, Assoc)>,
+//!    value!(plus,  (Op::Add, 1, Assoc::Left)) |
+//!    value!(times, (Op::Mul, 2, Assoc::Left)) |
+//!    value!(caret, (Op::Pow, 3, Assoc::Right))
+//!  );
+//! ```
+//!
+//! The expression tree is built by a callback receiving each `Operation`
+
+use internal::{IResult,Needed};
+use internal::IResult::*;
+use util::Offset;
+
+/// associativity of an infix operator
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum Assoc {
+  Left,
+  Right
+}
+
+/// an operator and its operands, given to the callback building the expression
+#[derive(Debug,PartialEq,Eq,Clone)]
+pub enum Operation<P,E> {
+  Prefix(P, E),
+  Infix(E, P, E),
+  Postfix(E, P)
+}
+
+/// parses an expression with precedence climbing
+///
+/// * atom parses the operands (numbers, variables, parenthesized expressions...)
+/// * prefix parses a prefix operator and returns it with its precedence
+/// * infix parses an infix operator and returns it with its precedence and associativity
+/// * postfix parses a postfix operator and returns it with its precedence
+/// * build combines an operator and its operands
+///
+/// operators with a higher precedence bind tighter. If there are no operators of a kind,
+/// the table can be a parser that always fails, like `&|_| Error(Code(0))`. The errors
+/// located with `locate!` are relocated to the beginning of the expression
+///
+/// ```ignore
+///  fn expr(input:&[u8]) -> IResult<&[u8], i64> {
+///    precedence(input, &atom, &prefix, &infix, &postfix, &|op| match op {
+///      Operation::Prefix(Op::Neg, e)       => -e,
+///      Operation::Infix(a, Op::Add, b)     => a + b,
+///      Operation::Infix(a, Op::Mul, b)     => a * b,
+///      ...
+///    })
+///  }
+///
+///  assert_eq!(expr(b"1+2*-3;"), Done(b";", -5));
+/// ```
+pub fn precedence<'a,P,E>(input:   &'a [u8],
+                          atom:    &Fn(&'a [u8]) -> IResult<&'a [u8], E>,
+                          prefix:  &Fn(&'a [u8]) -> IResult<&'a [u8], (P, u32)>,
+                          infix:   &Fn(&'a [u8]) -> IResult<&'a [u8], (P, u32, Assoc)>,
+                          postfix: &Fn(&'a [u8]) -> IResult<&'a [u8], (P, u32)>,
+                          build:   &Fn(Operation<P,E>) -> E) -> IResult<&'a [u8], E> {
+  climb(input, 0, atom, prefix, infix, postfix, build)
+}
+
+fn climb<'a,P,E>(input:     &'a [u8],
+                 min_prec:  u32,
+                 atom:      &Fn(&'a [u8]) -> IResult<&'a [u8], E>,
+                 prefix:    &Fn(&'a [u8]) -> IResult<&'a [u8], (P, u32)>,
+                 infix:     &Fn(&'a [u8]) -> IResult<&'a [u8], (P, u32, Assoc)>,
+                 postfix:   &Fn(&'a [u8]) -> IResult<&'a [u8], (P, u32)>,
+                 build:     &Fn(Operation<P,E>) -> E) -> IResult<&'a [u8], E> {
+  let (mut i, mut lhs) = match prefix(input) {
+    Done(i, (op, prec)) => {
+      match climb(i, prec, atom, prefix, infix, postfix, build) {
+        Done(i2, operand) => (i2, build(Operation::Prefix(op, operand))),
+        Error(e)          => return Error(e.relocate(input, i)),
+        Incomplete(n)     => return Incomplete(needed(input, i, n))
+      }
+    },
+    Incomplete(n)       => return Incomplete(n),
+    Error(_)            => {
+      match atom(input) {
+        Done(i, operand) => (i, operand),
+        Error(e)         => return Error(e),
+        Incomplete(n)    => return Incomplete(n)
+      }
+    }
+  };
+
+  // at the end of the input, the postfix and infix parsers return Incomplete if an
+  // operator could follow
+  loop {
+    match postfix(i) {
+      Done(i2, (op, prec)) => {
+        if prec < min_prec {
+          break;
+        }
+        lhs = build(Operation::Postfix(lhs, op));
+        i   = i2;
+        continue;
+      },
+      Incomplete(n)        => return Incomplete(needed(input, i, n)),
+      Error(_)             => {}
+    }
+
+    match infix(i) {
+      Done(i2, (op, prec, assoc)) => {
+        if prec < min_prec {
+          break;
+        }
+        let next_prec = match assoc {
+          Assoc::Left  => prec + 1,
+          Assoc::Right => prec
+        };
+        match climb(i2, next_prec, atom, prefix, infix, postfix, build) {
+          Done(i3, rhs) => {
+            lhs = build(Operation::Infix(lhs, op, rhs));
+            i   = i3;
+          },
+          Error(e)      => return Error(e.relocate(input, i2)),
+          Incomplete(n) => return Incomplete(needed(input, i2, n))
+        }
+      },
+      Incomplete(n)               => return Incomplete(needed(input, i, n)),
+      Error(_)                    => break
+    }
+  }
+
+  Done(i, lhs)
+}
+
+/// converts the Needed of a parser applied on `remaining` to a Needed relative to `input`
+fn needed(input: &[u8], remaining: &[u8], n: Needed) -> Needed {
+  match n {
+    Needed::Unknown => Needed::Unknown,
+    Needed::Size(s) => Needed::Size(input.offset(remaining) as u32 + s)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use internal::{ConversionError,IResult,Needed};
+  use internal::IResult::*;
+  use internal::Err::*;
+  use nom::digit;
+  use std::str;
+
+  #[derive(Debug,PartialEq,Eq,Clone,Copy)]
+  enum Op {
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Pow,
+    Fact
+  }
+
+  tag!(minus  "-");
+  tag!(plus   "+");
+  tag!(times  "*");
+  tag!(caret  "^");
+  tag!(bang   "!");
+  tag!(lparen "(");
+  tag!(rparen ")");
+
+  alt!(prefix<&[u8], (Op, u32)>, value!(minus, (Op::Neg, 4)));
+  alt!(infix<&[u8], (Op, u32, Assoc)>,
+    value!(plus,  (Op::Add, 1, Assoc::Left))  |
+    value!(minus, (Op::Sub, 1, Assoc::Left))  |
+    value!(times, (Op::Mul, 2, Assoc::Left))  |
+    value!(caret, (Op::Pow, 3, Assoc::Right))
+  );
+  alt!(postfix<&[u8], (Op, u32)>, value!(bang, (Op::Fact, 5)));
+
+  map_res!(number<&[u8], i64>, map_res!(digit, str::from_utf8), str::FromStr::from_str);
+
+  fn atom(input:&[u8]) -> IResult<&[u8], i64> {
+    locate!(input, alt!(number | delimited!(lparen, expr, rparen)))
+  }
+
+  fn eval(op: Operation<Op, i64>) -> i64 {
+    match op {
+      Operation::Prefix(Op::Neg, e)     => -e,
+      Operation::Infix(a, Op::Add, b)   => a + b,
+      Operation::Infix(a, Op::Sub, b)   => a - b,
+      Operation::Infix(a, Op::Mul, b)   => a * b,
+      Operation::Infix(a, Op::Pow, b)   => (0..b).fold(1, |acc, _| acc * a),
+      Operation::Postfix(e, Op::Fact)   => (1..e+1).fold(1, |acc, x| acc * x),
+      _                                 => unreachable!()
+    }
+  }
+
+  fn expr(input:&[u8]) -> IResult<&[u8], i64> {
+    precedence(input, &atom, &prefix, &infix, &postfix, &eval)
+  }
+
+  fn show(op: Operation<Op, String>) -> String {
+    match op {
+      Operation::Prefix(o, e)    => format!("({:?} {})", o, e),
+      Operation::Infix(a, o, b)  => format!("({} {:?} {})", a, o, b),
+      Operation::Postfix(e, o)   => format!("({} {:?})", e, o)
+    }
+  }
+
+  fn tree(input:&[u8]) -> IResult<&[u8], String> {
+    precedence(input, &|i| number(i).map(|n| n.to_string()), &prefix, &infix, &postfix, &show)
+  }
+
+  #[test]
+  fn evaluate() {
+    assert_eq!(expr(b"1+2*3;"), Done(&b";"[..], 7));
+    assert_eq!(expr(b"(1+2)*3;"), Done(&b";"[..], 9));
+    assert_eq!(expr(b"2^3^2;"), Done(&b";"[..], 512));
+    assert_eq!(expr(b"10-2-3;"), Done(&b";"[..], 5));
+    assert_eq!(expr(b"-2^2;"), Done(&b";"[..], 4));
+    assert_eq!(expr(b"3!*2;"), Done(&b";"[..], 12));
+    assert_eq!(expr(b"1+2*-3;"), Done(&b";"[..], -5));
+  }
+
+  #[test]
+  fn associativity() {
+    assert_eq!(tree(b"1-2-3;"), Done(&b";"[..], "((1 Sub 2) Sub 3)".to_string()));
+    assert_eq!(tree(b"1^2^3;"), Done(&b";"[..], "(1 Pow (2 Pow 3))".to_string()));
+    assert_eq!(tree(b"-1+2!*3;"), Done(&b";"[..], "((Neg 1) Add ((2 Fact) Mul 3))".to_string()));
+  }
+
+  #[test]
+  fn partial() {
+    // an operator could follow at the end of the input
+    assert_eq!(expr(b"2!"), Incomplete(Needed::Size(3)));
+    assert_eq!(expr(b"2!+1;"), Done(&b";"[..], 3));
+    assert_eq!(expr(b"1+2"), Incomplete(Needed::Size(4)));
+    assert_eq!(expr(b"1+"), Incomplete(Needed::Size(3)));
+  }
+
+  #[test]
+  fn errors() {
+    let empty = ConversionError::new("".parse::<i64>().unwrap_err());
+    let atom_error = |p| Error(Position(p, Box::new(Alt(vec![Conversion(empty.clone()), Code(0)]))));
+    assert_eq!(expr(b"*2"), atom_error(0));
+    assert_eq!(expr(b"1+*2"), atom_error(2));
+    assert_eq!(expr(b"1*-*2"), atom_error(3));
+    // the operator after "1" is not known, the expression stops there
+    assert_eq!(expr(b"1/2"), Done(&b"/2"[..], 1));
+    assert_eq!(expr(b"(1+2"), Incomplete(Needed::Size(5)));
+  }
+}
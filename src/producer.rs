@@ -15,26 +15,31 @@
 //!    let mut p = producer;
 //!
 //!    // create the parsing function
-//!    fn parser(par: IResult<(),&[u8]>) -> IResult<&[u8],()> {
-//!      par.map_res(str::from_utf8).flat_map(local_print);
-//!      Done(b"", ())
+//!    fn parser(input: &[u8]) -> IResult<&[u8],()> {
+//!      Done(input, input).map_res(str::from_utf8).flat_map(local_print);
+//!      Done(&input[input.len()..], ())
 //!    }
 //!
 //!    // adapt the parsing function to the producer
 //!    pusher!(push, parser);
 //!    // get started
-//!    push(&mut p);
+//!    push(&mut p).unwrap();
 //!  });
 //! ```
 
 use internal::*;
+use internal::Err::*;
 use self::ProducerState::*;
 
 use std::fs::File;
 use std::path::Path;
 use std::num::Int;
 use std::io;
-use std::io::{Read,Seek,SeekFrom};
+use std::io::{Read,Seek,SeekFrom,ErrorKind};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd,RawFd};
+#[cfg(unix)]
+use libc;
 
 /// Holds the data producer's current state
 ///
@@ -46,65 +51,209 @@ use std::io::{Read,Seek,SeekFrom};
 /// * Data contains already parsed data
 ///
 /// * ProducerError indicates something went wrong
-#[derive(Debug,PartialEq,Eq)]
+///
+/// * IoError holds the error returned by the producer's reader
+#[derive(Debug)]
 pub enum ProducerState<O> {
   Eof(O),
   Continue,
   Data(O),
   ProducerError(Err),
+  IoError(io::Error),
+}
+
+// io::Error cannot be compared, so the IoError states are equal if their errors have the same kind
+impl<O:PartialEq> PartialEq for ProducerState<O> {
+  fn eq(&self, other: &ProducerState<O>) -> bool {
+    match (self, other) {
+      (&Eof(ref a),           &Eof(ref b))           => a == b,
+      (&Continue,             &Continue)             => true,
+      (&Data(ref a),          &Data(ref b))          => a == b,
+      (&ProducerError(ref a), &ProducerError(ref b)) => a == b,
+      (&IoError(ref a),       &IoError(ref b))       => a.kind() == b.kind(),
+      _                                              => false
+    }
+  }
 }
 
+impl<O:Eq> Eq for ProducerState<O> {}
+
 /// A producer implements the produce method, currently working with u8 arrays
+///
+/// After `produce` returned `Continue`, `wait` blocks until data can be produced
+/// again. By default, it returns immediately
 pub trait Producer {
   fn produce(&mut self)                   -> ProducerState<&[u8]>;
   fn seek(&mut self,   position:SeekFrom) -> Option<u64>;
+
+  fn wait(&mut self) -> io::Result<()> {
+    Ok(())
+  }
 }
 
-/// Can produce data from a file
+/// Can produce data from any reader: a socket, a pipe, stdin...
 ///
-/// the size field is the size of v, the internal buffer
-pub struct FileProducer {
-  size: usize,
-  file: File,
-  v:    Vec<u8>
+/// the size field is the size of v, the internal buffer.
+/// The reader cannot seek, see `SeekableProducer` for that
+pub struct ReadProducer<R> {
+  size:   usize,
+  reader: R,
+  v:      Vec<u8>
 }
 
-impl FileProducer {
-  pub fn new(filename: &str, buffer_size: usize) -> io::Result<FileProducer> {
-    File::open(&Path::new(filename)).map(|f| {
-      FileProducer {size: buffer_size, file: f, v: Vec::with_capacity(buffer_size)}
-    })
+impl<R:Read> ReadProducer<R> {
+  pub fn new(reader: R, buffer_size: usize) -> ReadProducer<R> {
+    ReadProducer {size: buffer_size, reader: reader, v: Vec::with_capacity(buffer_size)}
+  }
+
+  /// returns the underlying reader
+  pub fn into_inner(self) -> R {
+    self.reader
   }
 }
 
-impl Producer for FileProducer {
+impl<R:Read> Producer for ReadProducer<R> {
   fn produce(&mut self) -> ProducerState<&[u8]> {
     //let mut v = Vec::with_capacity(self.size);
     //self.v.clear();
     self.v.resize(self.size, 0);
-    match self.file.read(&mut self.v) {
-      Err(e) => {
-        //println!("producer error: {:?}", e);
-        match e.kind() {
-          //ErrorKind::NoProgress => Continue,
-          //ErrorKind::EndOfFile  => Eof(&self.v[..]),
-          _          => ProducerError(0)
-        }
-      },
-      Ok(n)  => {
-        //println!("read: {} bytes\ndata:\n{}", n, (&self.v).to_hex(8));
-        self.v.truncate(n);
-        if n == 0 {
-          Eof(&self.v[..])
-        } else {
-          Data(&self.v[..])
+    loop {
+      match self.reader.read(&mut self.v) {
+        Err(e) => {
+          //println!("producer error: {:?}", e);
+          match e.kind() {
+            // the read was interrupted by a signal before reading anything, try again
+            ErrorKind::Interrupted => continue,
+            // no data available right now on a non blocking reader
+            ErrorKind::WouldBlock  => return Continue,
+            _                      => return IoError(e)
+          }
+        },
+        Ok(n)  => {
+          //println!("read: {} bytes\ndata:\n{}", n, (&self.v).to_hex(8));
+          self.v.truncate(n);
+          if n == 0 {
+            return Eof(&self.v[..])
+          } else {
+            return Data(&self.v[..])
+          }
         }
       }
     }
   }
 
+  fn seek(&mut self, _: SeekFrom) -> Option<u64> {
+    None
+  }
+}
+
+/// Can produce data from a reader that can seek, like a file or a `Cursor`
+pub struct SeekableProducer<R> {
+  producer: ReadProducer<R>
+}
+
+impl<R:Read+Seek> SeekableProducer<R> {
+  pub fn from_reader(reader: R, buffer_size: usize) -> SeekableProducer<R> {
+    SeekableProducer {producer: ReadProducer::new(reader, buffer_size)}
+  }
+
+  /// returns the underlying reader
+  pub fn into_inner(self) -> R {
+    self.producer.into_inner()
+  }
+}
+
+impl<R:Read+Seek> Producer for SeekableProducer<R> {
+  fn produce(&mut self) -> ProducerState<&[u8]> {
+    self.producer.produce()
+  }
+
   fn seek(&mut self, position: SeekFrom) -> Option<u64> {
-    self.file.seek(position).ok()
+    self.producer.reader.seek(position).ok()
+  }
+}
+
+/// Can produce data from a file
+pub type FileProducer = SeekableProducer<File>;
+
+impl SeekableProducer<File> {
+  pub fn new(filename: &str, buffer_size: usize) -> io::Result<FileProducer> {
+    File::open(&Path::new(filename)).map(|f| {
+      SeekableProducer::from_reader(f, buffer_size)
+    })
+  }
+}
+
+/// Can produce data from a non blocking file descriptor: a socket, a pipe...
+///
+/// `produce` returns `Continue` when no data is available, and `wait` blocks
+/// in poll(2) until the file descriptor is readable, so `Consumer::run` does
+/// not spin while waiting for the network
+#[cfg(unix)]
+pub struct FdProducer<R> {
+  producer: ReadProducer<R>
+}
+
+#[cfg(unix)]
+impl<R:Read+AsRawFd> FdProducer<R> {
+  /// switches the file descriptor of the reader to non blocking mode
+  pub fn new(reader: R, buffer_size: usize) -> io::Result<FdProducer<R>> {
+    try!(set_nonblocking(reader.as_raw_fd()));
+    Ok(FdProducer {producer: ReadProducer::new(reader, buffer_size)})
+  }
+
+  /// returns the underlying reader
+  pub fn into_inner(self) -> R {
+    self.producer.into_inner()
+  }
+}
+
+#[cfg(unix)]
+impl<R:Read+AsRawFd> Producer for FdProducer<R> {
+  fn produce(&mut self) -> ProducerState<&[u8]> {
+    self.producer.produce()
+  }
+
+  fn seek(&mut self, _: SeekFrom) -> Option<u64> {
+    None
+  }
+
+  fn wait(&mut self) -> io::Result<()> {
+    wait_readable(self.producer.reader.as_raw_fd(), -1).map(|_| ())
+  }
+}
+
+/// sets the O_NONBLOCK flag of a file descriptor
+#[cfg(unix)]
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+  unsafe {
+    let flags = libc::fcntl(fd, libc::F_GETFL);
+    if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) == -1 {
+      return Err(io::Error::last_os_error())
+    }
+  }
+  Ok(())
+}
+
+/// waits until a file descriptor is readable, or for `timeout_ms` milliseconds
+/// (-1 waits indefinitely)
+///
+/// returns false if the timeout expired. A closed or failed file descriptor
+/// is readable: the next read returns the end of file or the error
+#[cfg(unix)]
+pub fn wait_readable(fd: RawFd, timeout_ms: i32) -> io::Result<bool> {
+  let mut pfd = libc::pollfd { fd: fd, events: libc::POLLIN, revents: 0 };
+  loop {
+    match unsafe { libc::poll(&mut pfd, 1, timeout_ms) } {
+      -1 => {
+        let e = io::Error::last_os_error();
+        if e.kind() != ErrorKind::Interrupted {
+          return Err(e)
+        }
+      },
+      0  => return Ok(false),
+      _  => return Ok(true)
+    }
   }
 }
 
@@ -149,7 +298,7 @@ impl<'x> Producer for MemProducer<'x> {
       self.index = self.length;
       res
     } else {
-      ProducerError(0)
+      ProducerError(Code(0))
     }
   }
 
@@ -193,7 +342,9 @@ impl<'x> Producer for MemProducer<'x> {
 
 /// Prepares a parser function for a push pipeline
 ///
-/// It creates a function that accepts a producer and immediately starts parsing the data sent
+/// It creates a function that accepts a producer and immediately starts parsing the data sent.
+/// That function stops at the end of the data, and returns an error if the producer or the
+/// parser fails
 ///
 /// # Example
 ///
@@ -204,15 +355,16 @@ impl<'x> Producer for MemProducer<'x> {
 /// let mut p = MemProducer::new(b"abcdefgh", 8);
 ///
 /// pusher!(ps, pr);
-/// ps(&mut p);
+/// ps(&mut p).unwrap();
 /// ```
 #[macro_export]
 macro_rules! pusher (
   ($name:ident, $f:expr) => (
     #[allow(unused_variables)]
-    fn $name(producer: &mut Producer) {
+    fn $name(producer: &mut Producer) -> Result<(), $crate::consumer::RunError> {
       let mut acc: Vec<u8> = Vec::new();
       loop {
+        let mut eof = false;
         let state = producer.produce();
         match state {
           ProducerState::Data(v) => {
@@ -225,9 +377,17 @@ macro_rules! pusher (
           }
           ProducerState::Eof(v) => {
             //println!("eof with {} bytes", v.len());
+            eof = true;
             acc.push_all(v)
           }
-          _ => {break;}
+          ProducerState::Continue => {
+            if let Err(e) = producer.wait() {
+              return Err($crate::consumer::RunError::Io(e))
+            }
+            continue;
+          }
+          ProducerState::ProducerError(e) => return Err($crate::consumer::RunError::Producer(e)),
+          ProducerState::IoError(e)       => return Err($crate::consumer::RunError::Io(e))
         }
         let mut v2: Vec<u8>  = Vec::new();
         v2.push_all(acc.as_slice());
@@ -235,7 +395,7 @@ macro_rules! pusher (
         match $f(v2.as_slice()) {
           IResult::Error(e)      => {
             //println!("error, stopping: {}", e);
-            break;
+            return Err($crate::consumer::RunError::Consumer(e))
           },
           IResult::Incomplete(_) => {
             //println!("incomplete");
@@ -246,7 +406,11 @@ macro_rules! pusher (
             acc.push_all(i);
           }
         }
+        if eof {
+          break;
+        }
       }
+      Ok(())
     }
   );
 );
@@ -256,8 +420,17 @@ mod tests {
   use super::*;
   use internal::{Needed,IResult};
   use internal::IResult::*;
+  use internal::Err::*;
+  use consumer::RunError;
   use std::fmt::Debug;
   use std::str;
+  use std::io::{self,Cursor,Read,Write,SeekFrom,ErrorKind};
+  #[cfg(unix)]
+  use std::os::unix::io::AsRawFd;
+  #[cfg(unix)]
+  use std::os::unix::net::UnixStream;
+  #[cfg(unix)]
+  use test_util::stream_pair;
   use map::*;
 
   fn local_print<'a,T: Debug>(input: T) -> IResult<T, ()> {
@@ -277,7 +450,7 @@ mod tests {
       local_print(data)
     }
     pusher!(ps, pr);
-    ps(&mut p);
+    assert!(ps(&mut p).is_ok());
     //let mut iterations: uint = 0;
     //let mut p = MemProducer::new(b"abcdefghi", 4);
     //p.push(|par| {iterations = iterations + 1; par.flat_map(print)});
@@ -296,11 +469,126 @@ mod tests {
         Done(b"",b"")
       }
       pusher!(ps, pr);
-      ps(&mut p);
+      assert!(ps(&mut p).is_ok());
       //assert!(false);
     });
   }
 
+  #[test]
+  fn read_producer() {
+    let mut p = ReadProducer::new(Cursor::new(b"abcdefgh".to_vec()), 5);
+    assert_eq!(p.produce(), ProducerState::Data(&b"abcde"[..]));
+    assert_eq!(p.produce(), ProducerState::Data(&b"fgh"[..]));
+    assert_eq!(p.produce(), ProducerState::Eof(&b""[..]));
+    assert_eq!(p.seek(SeekFrom::Start(0)), None);
+  }
+
+  #[test]
+  fn seekable_producer() {
+    let mut p = SeekableProducer::from_reader(Cursor::new(b"abcdefgh".to_vec()), 4);
+    assert_eq!(p.produce(), ProducerState::Data(&b"abcd"[..]));
+    assert_eq!(p.seek(SeekFrom::Current(-2)), Some(2));
+    assert_eq!(p.produce(), ProducerState::Data(&b"cdef"[..]));
+    assert_eq!(p.seek(SeekFrom::End(-1)), Some(7));
+    assert_eq!(p.produce(), ProducerState::Data(&b"h"[..]));
+    assert_eq!(p.into_inner().position(), 8);
+  }
+
+  // fails with the errors of the list, then reads the data
+  struct FailingReader {
+    errors: Vec<ErrorKind>,
+    data:   &'static [u8]
+  }
+
+  impl Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      if self.errors.is_empty() {
+        self.data.read(buf)
+      } else {
+        Err(io::Error::new(self.errors.remove(0), "failing reader"))
+      }
+    }
+  }
+
+  #[test]
+  fn read_errors() {
+    let reader = FailingReader { errors: vec![ErrorKind::Interrupted, ErrorKind::WouldBlock, ErrorKind::BrokenPipe], data: b"abcd" };
+    let mut p  = ReadProducer::new(reader, 4);
+    // the interrupted read is retried, then there is no data available yet
+    assert_eq!(p.produce(), ProducerState::Continue);
+    match p.produce() {
+      ProducerState::IoError(e) => assert_eq!(e.kind(), ErrorKind::BrokenPipe),
+      s                         => panic!("expected an IoError, got {:?}", s)
+    }
+    assert_eq!(p.produce(), ProducerState::Data(&b"abcd"[..]));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn fd_producer() {
+    let (a, mut b, reads) = stream_pair();
+    let mut p = FdProducer::new(a, 4).unwrap();
+    // nothing was written yet
+    assert_eq!(p.produce(), ProducerState::Continue);
+
+    b.write_all(b"abcdef").unwrap();
+    p.wait().unwrap();
+    assert_eq!(p.produce(), ProducerState::Data(&b"abcd"[..]));
+    assert_eq!(p.produce(), ProducerState::Data(&b"ef"[..]));
+    assert_eq!(p.produce(), ProducerState::Continue);
+    assert_eq!(reads.recv().unwrap(), 4);
+    assert_eq!(reads.recv().unwrap(), 2);
+
+    drop(b);
+    p.wait().unwrap();
+    assert_eq!(p.produce(), ProducerState::Eof(&b""[..]));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn wait_timeout() {
+    let (a, _b) = UnixStream::pair().unwrap();
+    assert_eq!(wait_readable(a.as_raw_fd(), 10).unwrap(), false);
+  }
+
+  // never has data, and fails to wait for it
+  struct Unavailable;
+
+  impl Producer for Unavailable {
+    fn produce(&mut self) -> ProducerState<&[u8]> {
+      ProducerState::Continue
+    }
+
+    fn seek(&mut self, _: SeekFrom) -> Option<u64> {
+      None
+    }
+
+    fn wait(&mut self) -> io::Result<()> {
+      Err(io::Error::new(ErrorKind::TimedOut, "no data"))
+    }
+  }
+
+  #[test]
+  fn pusher_errors() {
+    fn pr(data: &[u8]) -> IResult<&[u8], ()> {
+      Done(&data[data.len()..], ())
+    }
+    pusher!(ps, pr);
+    match ps(&mut Unavailable) {
+      Err(RunError::Io(ref e)) if e.kind() == ErrorKind::TimedOut => {},
+      r                                                         => panic!("expected an I/O error, got {:?}", r)
+    }
+
+    fn failing(_: &[u8]) -> IResult<&[u8], ()> {
+      Error(Code(1))
+    }
+    pusher!(pf, failing);
+    match pf(&mut MemProducer::new(b"abcdefgh", 4)) {
+      Err(RunError::Consumer(Code(1))) => {},
+      r                                => panic!("expected a parser error, got {:?}", r)
+    }
+  }
+
   #[test]
   fn accu() {
     fn f(input:&[u8]) -> IResult<&[u8],&[u8]> {
@@ -318,7 +606,7 @@ mod tests {
       r
     }
     pusher!(ps, pr );
-    ps(&mut p);
+    assert!(ps(&mut p).is_ok());
     //assert!(false);
   }
 
@@ -339,7 +627,7 @@ mod tests {
       r
     }
     pusher!(ps, pr );
-    ps(&mut p);
+    assert!(ps(&mut p).is_ok());
     //assert!(false);
   }
 }
@@ -0,0 +1,273 @@
+//! Parser profiling
+//!
+//! With the `profile` feature, the parsers generated by the macros with a name
+//! (`tag!`, `chain!`, `alt!`, `many0!`...) count their calls, their results, the bytes
+//! they consumed and the time spent in them, with and without the named parsers they
+//! call. The parsers are keyed by their module path, their name and the line where
+//! they are defined, like `mycrate::http::header:42`. `profile_report` lists the parsers
+//! of the current thread, hottest first:
+//!
+//! ```ignore
+//!  parse_file(data);
+//!
+//!  for (name, stats) in profile_report() {
+//!    println!("{}: {} calls, {} bytes, {} ns ({} ns self)", name, stats.calls, stats.consumed,
+//!      stats.time_ns, stats.self_time_ns);
+//!  }
+//! ```
+//!
+//! Without that feature, the counting functions are empty and compile to nothing,
+//! and the report is empty
+
+use internal::IResult;
+use util::AsBytes;
+
+#[cfg(feature = "profile")]
+use std::cell::RefCell;
+#[cfg(feature = "profile")]
+use std::collections::HashMap;
+#[cfg(feature = "profile")]
+use internal::IResult::*;
+#[cfg(feature = "profile")]
+use util::Offset;
+#[cfg(feature = "profile")]
+use time;
+
+/// counters of a named parser
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub struct ParserStats {
+  /// number of times the parser was applied
+  pub calls:      u64,
+  /// number of `Done` results
+  pub done:       u64,
+  /// number of `Error` results
+  pub error:      u64,
+  /// number of `Incomplete` results
+  pub incomplete: u64,
+  /// total of the bytes consumed by the `Done` results
+  pub consumed:   u64,
+  /// time spent in the parser, including the parsers it calls, in nanoseconds.
+  /// The recursive calls of a parser are counted once, in its outermost call
+  pub time_ns:    u64,
+  /// time spent in the parser, minus the time spent in the named parsers it calls,
+  /// in nanoseconds
+  pub self_time_ns: u64
+}
+
+#[cfg(feature = "profile")]
+impl ParserStats {
+  fn new() -> ParserStats {
+    ParserStats {
+      calls:      0,
+      done:       0,
+      error:      0,
+      incomplete: 0,
+      consumed:   0,
+      time_ns:    0,
+      self_time_ns: 0
+    }
+  }
+}
+
+#[cfg(feature = "profile")]
+thread_local!(static STATS: RefCell<HashMap<&'static str, ParserStats>> = RefCell::new(HashMap::new()));
+
+// time spent in the named parsers called by each running parser, innermost last
+#[cfg(feature = "profile")]
+thread_local!(static CHILDREN: RefCell<Vec<u64>> = RefCell::new(Vec::new()));
+
+// number of running calls of each parser, more than one for a recursive parser
+#[cfg(feature = "profile")]
+thread_local!(static DEPTH: RefCell<HashMap<&'static str, usize>> = RefCell::new(HashMap::new()));
+
+/// start time of a parser, given back to `profile_end`
+#[cfg(feature = "profile")]
+pub type ProfileStart = u64;
+
+/// start time of a parser, given back to `profile_end`
+#[cfg(not(feature = "profile"))]
+pub type ProfileStart = ();
+
+/// returns the start time of a parser, used by `trace_parser!`
+///
+/// `name` is the key of the parser: its module path, its name and its line
+#[cfg(feature = "profile")]
+pub fn profile_start(name: &'static str) -> ProfileStart {
+  CHILDREN.with(|c| c.borrow_mut().push(0));
+  DEPTH.with(|d| *d.borrow_mut().entry(name).or_insert(0) += 1);
+  time::precise_time_ns()
+}
+
+/// updates the counters of a parser with its result, used by `trace_parser!`
+///
+/// `name` is the key of the parser given to `profile_start`
+#[cfg(feature = "profile")]
+pub fn profile_end<I:AsBytes,O>(name: &'static str, input: &I, start: ProfileStart, res: &IResult<I,O>) {
+  let elapsed  = time::precise_time_ns() - start;
+  let nested   = DEPTH.with(|d| {
+    let mut depths = d.borrow_mut();
+    let depth      = depths.entry(name).or_insert(1);
+    *depth -= 1;
+    *depth > 0
+  });
+  let children = CHILDREN.with(|c| {
+    let mut stack = c.borrow_mut();
+    let children  = stack.pop().unwrap_or(0);
+    if let Some(parent) = stack.last_mut() {
+      *parent += elapsed;
+    }
+    children
+  });
+  STATS.with(|s| {
+    let mut map   = s.borrow_mut();
+    let stats     = map.entry(name).or_insert(ParserStats::new());
+    stats.calls   += 1;
+    // the outermost call includes the time of the recursive ones
+    if !nested {
+      stats.time_ns += elapsed;
+    }
+    stats.self_time_ns += elapsed - ::std::cmp::min(elapsed, children);
+    match *res {
+      Done(ref i,_)   => {
+        stats.done     += 1;
+        stats.consumed += input.as_bytes().offset(i.as_bytes()) as u64;
+      },
+      Error(_)        => stats.error      += 1,
+      Incomplete(_)   => stats.incomplete += 1
+    }
+  })
+}
+
+/// returns the counters of the parsers applied in the current thread,
+/// sorted by decreasing time spent
+#[cfg(feature = "profile")]
+pub fn profile_report() -> Vec<(&'static str, ParserStats)> {
+  let mut report: Vec<(&'static str, ParserStats)> = STATS.with(|s| {
+    s.borrow().iter().map(|(name, stats)| (*name, *stats)).collect()
+  });
+  report.sort_by(|a, b| b.1.time_ns.cmp(&a.1.time_ns));
+  report
+}
+
+/// resets the counters of the current thread
+#[cfg(feature = "profile")]
+pub fn profile_reset() {
+  STATS.with(|s| s.borrow_mut().clear());
+  CHILDREN.with(|c| c.borrow_mut().clear());
+  DEPTH.with(|d| d.borrow_mut().clear())
+}
+
+#[cfg(not(feature = "profile"))]
+#[inline(always)]
+pub fn profile_start(_: &'static str) -> ProfileStart {}
+
+#[cfg(not(feature = "profile"))]
+#[inline(always)]
+pub fn profile_end<I:AsBytes,O>(_: &'static str, _: &I, _: ProfileStart, _: &IResult<I,O>) {}
+
+#[cfg(not(feature = "profile"))]
+pub fn profile_report() -> Vec<(&'static str, ParserStats)> {
+  Vec::new()
+}
+
+#[cfg(not(feature = "profile"))]
+pub fn profile_reset() {}
+
+#[cfg(all(test, feature = "profile"))]
+mod tests {
+  use super::*;
+  use internal::IResult;
+  use internal::IResult::*;
+  use internal::Needed;
+
+  mod other {
+    use internal::{IResult,Needed};
+    use internal::IResult::*;
+
+    tag!(x "cd");
+
+    pub fn parse(input: &[u8]) -> IResult<&[u8], &[u8]> {
+      x(input)
+    }
+  }
+
+  // the counters of the parsers with that module path and name, whatever their line
+  fn named(report: &[(&'static str, ParserStats)], name: &str) -> Vec<ParserStats> {
+    let mut found: Vec<ParserStats> = report.iter().filter(|r| {
+      match r.0.rfind(':') {
+        Some(pos) => &r.0[..pos] == name,
+        None      => false
+      }
+    }).map(|r| r.1).collect();
+    found.sort_by(|a, b| b.calls.cmp(&a.calls));
+    found
+  }
+
+  fn other_x(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    tag!(x "ef");
+    x(input)
+  }
+
+  #[test]
+  fn counters() {
+    tag!(x "ab");
+    tag!(y "cd");
+    alt!(xy<&[u8], &[u8]>, x | y);
+    many0!(multi<&[u8], &[u8]> xy);
+
+    profile_reset();
+    assert_eq!(multi(&b"abcdab;;"[..]), Done(&b";;"[..], vec![&b"ab"[..], &b"cd"[..], &b"ab"[..]]));
+
+    // the self times of the parsers add up to the time of the outermost one
+    let report = profile_report();
+    let total = report.iter().fold(0, |acc, r| acc + r.1.self_time_ns);
+    let m = named(&report, "nom::profile::tests::multi")[0];
+    assert_eq!(total, m.time_ns);
+    for r in report.iter() {
+      assert!(r.1.self_time_ns <= r.1.time_ns);
+    }
+
+    assert_eq!(x(&b"a"[..]), Incomplete(Needed::Size(2)));
+    assert_eq!(other::parse(&b"cd"[..]), Done(&b""[..], &b"cd"[..]));
+    assert_eq!(other_x(&b"ef"[..]), Done(&b""[..], &b"ef"[..]));
+
+    let report = profile_report();
+    for w in report.windows(2) {
+      assert!(w[0].1.time_ns >= w[1].1.time_ns);
+    }
+
+    let m = named(&report, "nom::profile::tests::multi")[0];
+    assert_eq!((m.calls, m.done, m.consumed), (1, 1, 6));
+    let a = named(&report, "nom::profile::tests::xy")[0];
+    assert_eq!((a.calls, a.done, a.error, a.consumed), (4, 3, 1, 6));
+    // a parser with the same name in another function of the module has its own counters
+    let t = named(&report, "nom::profile::tests::x");
+    assert_eq!(t.len(), 2);
+    assert_eq!((t[0].calls, t[0].done, t[0].error, t[0].incomplete, t[0].consumed), (5, 2, 2, 1, 4));
+    assert_eq!((t[1].calls, t[1].done, t[1].consumed), (1, 1, 2));
+    // and in another module
+    let o = named(&report, "nom::profile::tests::other::x");
+    assert_eq!(o.len(), 1);
+    assert_eq!((o[0].calls, o[0].done, o[0].consumed), (1, 1, 2));
+
+    profile_reset();
+    assert!(profile_report().is_empty());
+  }
+
+  #[test]
+  fn recursion() {
+    tag!(lparen "(");
+    tag!(rparen ")");
+    preceded!(nested<&[u8], &[u8]>, lparen, alt!(nested | rparen));
+
+    profile_reset();
+    assert_eq!(nested(&b"((())"[..]), Done(&b")"[..], &b")"[..]));
+
+    // the recursive calls are not added again to the time of the outermost one
+    let report = profile_report();
+    let n = named(&report, "nom::profile::tests::nested")[0];
+    assert_eq!(n.calls, 4);
+    let total = report.iter().fold(0, |acc, r| acc + r.1.self_time_ns);
+    assert_eq!(n.time_ns, total);
+  }
+}
@@ -0,0 +1,34 @@
+//! helpers shared by the tests of the producers and the consumers
+
+use std::io::{self,Read};
+use std::os::unix::io::{AsRawFd,RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{channel,Receiver,Sender};
+
+/// reading end of a socket pair, sending the size of each successful read on a channel
+pub struct CountedStream {
+  stream: UnixStream,
+  reads:  Sender<usize>
+}
+
+impl Read for CountedStream {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = try!(self.stream.read(buf));
+    // the receiver may be gone at the end of a test
+    let _ = self.reads.send(n);
+    Ok(n)
+  }
+}
+
+impl AsRawFd for CountedStream {
+  fn as_raw_fd(&self) -> RawFd {
+    self.stream.as_raw_fd()
+  }
+}
+
+/// returns the reading and writing ends of a local socket pair, and the sizes of the reads
+pub fn stream_pair() -> (CountedStream, UnixStream, Receiver<usize>) {
+  let (a, b)          = UnixStream::pair().unwrap();
+  let (sender, reads) = channel();
+  (CountedStream { stream: a, reads: sender }, b, reads)
+}
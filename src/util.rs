@@ -1,3 +1,5 @@
+use internal::Needed;
+
 
 pub trait HexDisplay {
       /// Converts the value of `self` to a hex value, returning the owned
@@ -82,3 +84,240 @@ impl AsBytes for [u8] {
     self
   }
 }
+
+/// position of a sub-slice relative to the slice it was taken from
+///
+/// parsers return slices of their input, so the offset of a parser's remaining
+/// input in the original input gives an absolute position in that input:
+///
+/// ```ignore
+///  let input = &b"abcdefgh"[..];
+///  if let Done(remaining, _) = tag_abcd(input) {
+///    assert_eq!(input.offset(remaining), 4);
+///  }
+/// ```
+pub trait Offset {
+  /// offset of the beginning of `second` from the beginning of `self`
+  ///
+  /// `second` must be a sub-slice of `self`, this is checked in debug builds
+  fn offset(&self, second: &Self) -> usize;
+
+  /// offset of the beginning of `second` from the beginning of `self`,
+  /// or None if `second` is not a sub-slice of `self`
+  fn checked_offset(&self, second: &Self) -> Option<usize>;
+}
+
+impl Offset for [u8] {
+  fn offset(&self, second: &[u8]) -> usize {
+    debug_assert!(self.checked_offset(second).is_some(), "the second slice is not a part of the first one");
+    let fst = self.as_ptr();
+    let snd = second.as_ptr();
+
+    (snd as usize).wrapping_sub(fst as usize)
+  }
+
+  fn checked_offset(&self, second: &[u8]) -> Option<usize> {
+    sub_slice_offset(self.as_ptr() as usize, self.len(), second.as_ptr() as usize, second.len())
+  }
+}
+
+impl Offset for str {
+  fn offset(&self, second: &str) -> usize {
+    debug_assert!(self.checked_offset(second).is_some(), "the second slice is not a part of the first one");
+    let fst = self.as_ptr();
+    let snd = second.as_ptr();
+
+    (snd as usize).wrapping_sub(fst as usize)
+  }
+
+  fn checked_offset(&self, second: &str) -> Option<usize> {
+    sub_slice_offset(self.as_ptr() as usize, self.len(), second.as_ptr() as usize, second.len())
+  }
+}
+
+fn sub_slice_offset(fst: usize, fst_len: usize, snd: usize, snd_len: usize) -> Option<usize> {
+  if snd >= fst && snd - fst <= fst_len && snd_len <= fst_len - (snd - fst) {
+    Some(snd - fst)
+  } else {
+    None
+  }
+}
+
+/// searches the earliest position in a stream where one of the needles starts
+///
+/// after an Incomplete, the same input is given again with more data at the end, and the
+/// search resumes where it stopped: every position before it was already checked, and
+/// cannot start a needle. The position is kept for the input that was searched: another
+/// input, or a shorter one, is searched from its beginning. If the data of the input
+/// was replaced in place, call `reset`
+///
+/// ```
+/// use nom::NeedleSearch;
+/// use nom::Needed;
+/// let needles = [&b"--boundary"[..], &b"\n\n"[..]];
+/// let mut search = NeedleSearch::new(&needles);
+/// let data = b"abc--boundary";
+/// assert_eq!(search.find(&data[..8]), Err(Needed::Size(13)));
+/// // only the data from offset 3 is checked again
+/// assert_eq!(search.find(&data[..]), Ok((3, 0)));
+/// ```
+pub struct NeedleSearch<'a> {
+  needles: &'a [&'a [u8]],
+  // the bytes a needle can start with
+  first:   [bool; 256],
+  // start of the input that was searched, and where its search stopped
+  base:    usize,
+  resume:  usize
+}
+
+impl<'a> NeedleSearch<'a> {
+  pub fn new(needles: &'a [&'a [u8]]) -> NeedleSearch<'a> {
+    let mut first = [false; 256];
+    for needle in needles.iter() {
+      match needle.first() {
+        Some(&b) => first[b as usize] = true,
+        // an empty needle starts anywhere
+        None     => first = [true; 256]
+      }
+    }
+    NeedleSearch { needles: needles, first: first, base: 0, resume: 0 }
+  }
+
+  /// offset in the input from which the next search starts
+  pub fn resume_offset(&self) -> usize {
+    self.resume
+  }
+
+  /// starts the next search from the beginning of the input
+  pub fn reset(&mut self) {
+    self.resume = 0;
+  }
+
+  /// returns the position of the earliest needle and its index
+  ///
+  /// If several needles start at the same position, the first one in the list is chosen.
+  /// If a needle is cut by the end of the input before a needle of lower priority
+  /// matches at the same position, or if none was found, returns how much input is
+  /// needed to know
+  pub fn find(&mut self, input: &[u8]) -> Result<(usize, usize), Needed> {
+    let base = input.as_ptr() as usize;
+    if base != self.base || self.resume > input.len() {
+      self.base   = base;
+      self.resume = 0;
+    }
+
+    for pos in self.resume..input.len() {
+      if !self.first[input[pos] as usize] {
+        continue;
+      }
+
+      let rest = &input[pos..];
+      for (idx, needle) in self.needles.iter().enumerate() {
+        if rest.len() >= needle.len() {
+          if &rest[..needle.len()] == *needle {
+            self.resume = 0;
+            return Ok((pos, idx))
+          }
+        } else if &needle[..rest.len()] == rest {
+          // the needle could continue in the next chunk, and it wins over the next ones
+          self.resume = pos;
+          return Err(Needed::Size((pos + needle.len()) as u32))
+        }
+      }
+    }
+
+    // the positions that were checked cannot start a needle, even with more data
+    self.resume = input.len();
+    match self.needles.iter().map(|n| n.len()).min() {
+      Some(0)   => {
+        self.resume = 0;
+        Ok((input.len(), self.needles.iter().position(|n| n.len() == 0).unwrap()))
+      },
+      Some(len) => Err(Needed::Size((input.len() + len) as u32)),
+      None      => Err(Needed::Unknown)
+    }
+  }
+}
+
+/// finds the earliest position in the input where one of the needles starts
+///
+/// returns that position and the index of the needle, like `NeedleSearch::find`,
+/// without keeping the position of the search for the next chunk
+pub fn find_any(input: &[u8], needles: &[&[u8]]) -> Result<(usize, usize), Needed> {
+  NeedleSearch::new(needles).find(input)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn offset() {
+    let a = &b"abcdefgh"[..];
+    let b = &a[2..];
+    let c = &a[..4];
+    let d = &a[8..];
+    assert_eq!(a.offset(b), 2);
+    assert_eq!(a.offset(c), 0);
+    assert_eq!(a.offset(d), 8);
+    assert_eq!(b.offset(&b[3..]), 3);
+
+    let s = "héllo world";
+    let w = &s[7..];
+    assert_eq!(s.offset(w), 7);
+  }
+
+  #[test]
+  fn checked_offset() {
+    let a = &b"abcdefgh"[..];
+    assert_eq!(a.checked_offset(&a[3..5]), Some(3));
+    assert_eq!(a.checked_offset(&a[8..]), Some(8));
+    assert_eq!((&a[2..]).checked_offset(a), None);
+    let v = a.to_vec();
+    assert_eq!(a.checked_offset(&v[8..]), None);
+
+    let s = "abcd";
+    let t = s.to_string();
+    assert_eq!(s.checked_offset(&s[1..]), Some(1));
+    assert_eq!(s.checked_offset(&t[2..]), None);
+  }
+
+  #[test]
+  fn needle_search() {
+    let needles = [&b"abcx"[..], &b"bc"[..]];
+    let mut search = NeedleSearch::new(&needles);
+    // the cut needle starts before the complete one, and could still match
+    assert_eq!(search.find(&b"zzabc"[..]), Err(Needed::Size(6)));
+    assert_eq!(search.resume_offset(), 2);
+    assert_eq!(search.find(&b"zzabcd"[..]), Ok((3, 1)));
+
+    // the positions already checked are skipped for the same input
+    let data = b"zzzzabcx";
+    assert_eq!(search.find(&data[..4]), Err(Needed::Size(6)));
+    assert_eq!(search.resume_offset(), 4);
+    assert_eq!(search.find(&data[..]), Ok((4, 0)));
+
+    // another input is searched from its beginning
+    assert_eq!(search.find(&b"zzzz"[..]), Err(Needed::Size(6)));
+    assert_eq!(search.find(&b"abcxzzbc"[..]), Ok((0, 0)));
+    let mut copy = data.to_vec();
+    assert_eq!(search.find(&copy[..4]), Err(Needed::Size(6)));
+    copy[0] = b'b';
+    copy[1] = b'c';
+    search.reset();
+    assert_eq!(search.find(&copy[..4]), Ok((0, 1)));
+
+    // a needle cut by the end of the input wins over the next ones at the same position
+    let needles = [&b"abcx"[..], &b"ab"[..]];
+    assert_eq!(find_any(&b"zzab"[..], &needles), Err(Needed::Size(6)));
+    assert_eq!(find_any(&b"zzabcd"[..], &needles), Ok((2, 1)));
+    assert_eq!(find_any(&b"zzabcx"[..], &needles), Ok((2, 0)));
+    // and a complete one wins over the next ones
+    let needles = [&b"ab"[..], &b"abcx"[..]];
+    assert_eq!(find_any(&b"zzab"[..], &needles), Ok((2, 0)));
+
+    assert_eq!(find_any(&b""[..], &needles), Err(Needed::Size(2)));
+    let empty = [&b"ab"[..], &b""[..]];
+    assert_eq!(find_any(&b"zz"[..], &empty), Ok((0, 1)));
+  }
+}
@@ -1,7 +1,7 @@
 #[macro_use]
 extern crate nom;
 
-use nom::{IResult,Needed,FlatMapOpt,line_ending,not_line_ending, space, alphanumeric, multispace};
+use nom::{IResult,Needed,line_ending,not_line_ending, space, alphanumeric, is_space};
 use nom::IResult::*;
 
 use std::str;
@@ -14,51 +14,53 @@ tag!(lsb       "[");
 tag!(rsb       "]");
 tag!(equal     "=");
 
+// the files are parsed complete, so the blank lines at the end
+// are matched with filter! instead of the streaming multispace
+fn is_blank(chr:u8) -> bool { is_space(chr) || chr == b'\r' || chr == b'\n' }
+filter!(blank is_blank);
+
 
 take_until_and_leave!(category_bytes "]");
-fn category_name(input: &[u8]) -> IResult<&[u8], &str> {
-  category_bytes(input).map_res(str::from_utf8)
-}
 
 take_until!(not_equal      "=");
 take_until_either_and_leave!(value_bytes "\n;");
 
-fn value_parser(input:&[u8]) -> IResult<&[u8], &str> {
-  value_bytes(input).map_res(str::from_utf8)
-}
-
-fn parameter_parser(input: &[u8]) -> IResult<&[u8], &str> {
-  alphanumeric(input).map_res(str::from_utf8)
-}
+map_res!(value_parser <&[u8], &str>, value_bytes, str::from_utf8);
 
 o!(comment_body     <&[u8], &[u8]>       semicolon ~ [ not_line_ending ]);
 o!(comment          <&[u8], ()>          comment_body ~ line_ending ~ [ empty_result ]);
 opt!(opt_comment    <&[u8], &[u8]>       comment_body);
 
 chain!(category     <&[u8], &str>,
-          lsb             ~
-    name: category_name   ~
-          rsb             ~
-          multispace?     ,
+          lsb                                      ~
+    name: map_res!(category_bytes, str::from_utf8) ~
+          rsb                                      ~
+          blank                                    ,
     ||{ name }
 );
 chain!(key_value    <&[u8],(&str,&str)>,
-    key: parameter_parser ~
-         space?           ~
-         equal            ~
-         space?           ~
-    val: value_parser     ~
-         space?           ~
-         comment_body?    ~
-         multispace?      ,
+    key: map_res!(alphanumeric, str::from_utf8) ~
+         space?                                 ~
+         equal                                  ~
+         space?                                 ~
+    val: value_parser                           ~
+         space?                                 ~
+         comment_body?                          ~
+         blank                                  ,
     ||{(key, val)}
 );
 
+recognize!(key_value_line <&[u8], &[u8]>, key_value);
+
+// the folds return Incomplete at the end of the input, unless their
+// items know that the file is complete
+complete!(complete_key_value <&[u8],(&str,&str)>, key_value);
+
 fn keys_and_values<'a>(input: &'a[u8], mut z: HashMap<&'a str, &'a str>) -> IResult<&'a[u8], HashMap<&'a str, &'a str> > {
   fold0_impl!(<&[u8], HashMap<&str, &str> >, | mut h:HashMap<&'a str, &'a str>, (k, v)| {
     h.insert(k,v);
     h
-  }, key_value, input, z);
+  }, complete_key_value, input, z);
 }
 
 fn keys_and_values_wrapper<'a>(input:&'a[u8]) -> IResult<&'a[u8], HashMap<&'a str, &'a str> > {
@@ -73,13 +75,14 @@ chain!(category_and_keys<&[u8],(&str,HashMap<&str,&str>)>,
     keys: keys_and_values_wrapper ,
     move ||{(category, keys)}
 );
+complete!(complete_category_and_keys<&[u8],(&str,HashMap<&str,&str>)>, category_and_keys);
 
 fn categories<'a>(input: &'a[u8]) -> IResult<&'a[u8], HashMap<&'a str, HashMap<&'a str, &'a str> > > {
   let z: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
   fold0_impl!(<&[u8], HashMap<&str, HashMap<&str, &str> > >, |mut h:HashMap<&'a str, HashMap<&'a str, &'a str> >, (k, v)| {
     h.insert(k,v);
     h
-  }, category_and_keys, input, z);
+  }, complete_category_and_keys, input, z);
 }
 
 #[test]
@@ -214,6 +217,19 @@ key = value2";
   assert_eq!(res, Done(ini_without_key_value, ("parameter", "value")));
 }
 
+#[test]
+fn parse_key_value_line_test() {
+  let ini_file = b"parameter=value;abc
+key = value2";
+
+  let line = b"parameter=value;abc
+";
+  let ini_without_key_value = b"key = value2";
+
+  let res = key_value_line(ini_file);
+  assert_eq!(res, Done(&ini_without_key_value[..], &line[..]));
+}
+
 #[test]
 fn parse_multiple_keys_and_values_test() {
   let ini_file = b"parameter=value;abc
@@ -263,6 +279,26 @@ key = value2
   assert_eq!(res, Done(ini_after_parser, ("abcd", expected_h)));
 }
 
+#[test]
+fn parse_whole_file_test() {
+  terminated!(ini_file_parser<&[u8], HashMap<&str, HashMap<&str, &str> > >, categories, eof!(true));
+
+  let ini_file = b"[abcd]
+parameter=value;abc
+
+[category]
+key4 = value4
+";
+
+  let ini_with_garbage = b"[abcd]
+parameter=value
+=garbage";
+
+  let res = ini_file_parser(ini_file);
+  assert_eq!(res.clone().map(|h| h.len()), Done(&b""[..], 2));
+  assert!(ini_file_parser(ini_with_garbage).is_err());
+}
+
 #[test]
 fn parse_multiple_categories_test() {
   let ini_file = b"[abcd]
@@ -1,27 +1,15 @@
 #[macro_use]
 extern crate nom;
 
-use nom::{HexDisplay,Needed,IResult,FlatMapOpt,Functor,FileProducer,be_u16,be_u32,be_u64,be_f32};
-use nom::{Consumer,ConsumerState};
+use nom::{HexDisplay,Needed,IResult,FileProducer,be_u16,be_u32,be_u64,be_f32};
+use nom::{Consumer,ConsumerState,Err};
 use nom::IResult::*;
 
 use std::str;
 use std::io::SeekFrom;
 
-fn mp4_box(input:&[u8]) -> IResult<&[u8], &[u8]> {
-  match be_u32(input) {
-    Done(i, offset) => {
-      let sz: usize = offset as usize;
-      if i.len() >= sz - 4 {
-        return Done(&i[(sz-4)..], &i[0..(sz-4)])
-      } else {
-        return Incomplete(Needed::Size(4 + offset as u32))
-      }
-    }
-    Error(e)      => Error(e),
-    Incomplete(e) => Incomplete(e)
-  }
-}
+// the size of a box includes the 4 bytes of the size
+length_bytes!(mp4_box<&[u8], &[u8]>, be_u32, -4);
 
 #[derive(PartialEq,Eq,Debug)]
 struct FileType<'a> {
@@ -32,43 +20,14 @@ struct FileType<'a> {
 
 #[allow(non_snake_case)]
 #[derive(Debug)]
-pub struct Mvhd32 {
-  version_flags: u32, // actually:
-  // version: u8,
-  // flags: u24       // 3 bytes
-  created_date:  u32,
-  modified_date: u32,
-  scale:         u32,
-  duration:      u32,
-  speed:         f32,
-  volume:        u16, // actually a 2 bytes decimal
-  /* 10 bytes reserved */
-  scaleA:        f32,
-  rotateB:       f32,
-  angleU:        f32,
-  rotateC:       f32,
-  scaleD:        f32,
-  angleV:        f32,
-  positionX:     f32,
-  positionY:     f32,
-  scaleW:        f32,
-  preview:       u64,
-  poster:        u32,
-  selection:     u64,
-  current_time:  u32,
-  track_id:      u32
-}
-
-#[allow(non_snake_case)]
-#[derive(Debug)]
-pub struct Mvhd64 {
+pub struct Mvhd {
   version_flags: u32, // actually:
   // version: u8,
   // flags: u24       // 3 bytes
-  created_date:  u64,
-  modified_date: u64,
+  created_date:  u64, // 32 bits in version 0
+  modified_date: u64, // 32 bits in version 0
   scale:         u32,
-  duration:      u64,
+  duration:      u64, // 32 bits in version 0
   speed:         f32,
   volume:        u16, // actually a 2 bytes decimal
   /* 10 bytes reserved */
@@ -89,64 +48,21 @@ pub struct Mvhd64 {
 }
 take!(ten_bytes 10);
 
-#[allow(non_snake_case)]
-chain!(mvhd32 <&[u8], MvhdBox>,
-  version_flags: be_u32 ~
+chain!(mvhd_dates32 <&[u8], (u64, u64, u32, u64)>,
   created_date:  be_u32 ~
   modified_date: be_u32 ~
   scale:         be_u32 ~
-  duration:      be_u32 ~
-  speed:         be_f32 ~
-  volume:        be_u16 ~ // actually a 2 bytes decimal
-              ten_bytes ~
-  scaleA:        be_f32 ~
-  rotateB:       be_f32 ~
-  angleU:        be_f32 ~
-  rotateC:       be_f32 ~
-  scaleD:        be_f32 ~
-  angleV:        be_f32 ~
-  positionX:     be_f32 ~
-  positionY:     be_f32 ~
-  scaleW:        be_f32 ~
-  preview:       be_u64 ~
-  poster:        be_u32 ~
-  selection:     be_u64 ~
-  current_time:  be_u32 ~
-  track_id:      be_u32,
-  ||{
-    MvhdBox::M32(Mvhd32 {
-      version_flags: version_flags,
-      created_date:  created_date,
-      modified_date: modified_date,
-      scale:         scale,
-      duration:      duration,
-      speed:         speed,
-      volume:        volume,
-      scaleA:        scaleA,
-      rotateB:       rotateB,
-      angleU:        angleU,
-      rotateC:       rotateC,
-      scaleD:        scaleD,
-      angleV:        angleV,
-      positionX:     positionX,
-      positionY:     positionY,
-      scaleW:        scaleW,
-      preview:       preview,
-      poster:        poster,
-      selection:     selection,
-      current_time:  current_time,
-      track_id:      track_id
-    })
-  }
+  duration:      be_u32,
+  ||{(created_date as u64, modified_date as u64, scale, duration as u64)}
 );
 
+tuple!(mvhd_dates64 <&[u8], (u64, u64, u32, u64)>, be_u64, be_u64, be_u32, be_u64);
+
 #[allow(non_snake_case)]
-chain!(mvhd64 <&[u8], MvhdBox>,
+chain!(mvhd_box <&[u8], Mvhd>,
   version_flags: be_u32 ~
-  created_date:  be_u64 ~
-  modified_date: be_u64 ~
-  scale:         be_u32 ~
-  duration:      be_u64 ~
+  dates32:       cond!(version_flags >> 24 == 0, mvhd_dates32) ~
+  dates64:       cond!(version_flags >> 24 != 0, mvhd_dates64) ~
   speed:         be_f32 ~
   volume:        be_u16 ~ // actually a 2 bytes decimal
               ten_bytes ~
@@ -165,7 +81,9 @@ chain!(mvhd64 <&[u8], MvhdBox>,
   current_time:  be_u32 ~
   track_id:      be_u32,
   ||{
-    MvhdBox::M64(Mvhd64 {
+    // exactly one of the date variants was parsed
+    let (created_date, modified_date, scale, duration) = dates32.or(dates64).unwrap();
+    Mvhd {
       version_flags: version_flags,
       created_date:  created_date,
       modified_date: modified_date,
@@ -187,16 +105,10 @@ chain!(mvhd64 <&[u8], MvhdBox>,
       selection:     selection,
       current_time:  current_time,
       track_id:      track_id
-    })
+    }
   }
 );
 
-#[derive(Debug)]
-pub enum MvhdBox {
-  M32(Mvhd32),
-  M64(Mvhd64)
-}
-
 #[derive(Debug)]
 pub enum MoovBox {
   Mdra,
@@ -204,7 +116,7 @@ pub enum MoovBox {
   Cmov,
   Rmra,
   Iods,
-  Mvhd(MvhdBox),
+  Mvhd(Mvhd),
   Clip,
   Trak,
   Udta
@@ -244,7 +156,9 @@ fn brand_name(input:&[u8]) -> IResult<&[u8],&str> {
   major_brand_bytes(input).map_res(str::from_utf8)
 }
 take!(major_brand_version 4);
-many0!(compatible_brands<&[u8], &str> brand_name);
+// the brands fill the rest of the box, which is parsed complete
+complete!(compatible_brand<&[u8], &str>, brand_name);
+many0!(compatible_brands<&[u8], &str> compatible_brand);
 
 fn filetype_parser<'a>(input: &'a[u8]) -> IResult<&'a [u8], FileType<'a> > {
   chaining_parser!(input,
@@ -254,95 +168,20 @@ fn filetype_parser<'a>(input: &'a[u8]) -> IResult<&'a [u8], FileType<'a> > {
     ||{FileType{major_brand: m, major_brand_version:v, compatible_brands: c}})
 }
 
-fn filetype_box_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  ftyp(input).map(|_| MP4BoxType::Ftyp)
-}
-
 tag!(moov_tag "moov");
-
-tag!(mdra    "mdra");
-fn moov_mdra_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  mdra(input).map(|_| MP4BoxType::Mdra)
-}
-
-tag!(dref    "dref");
-fn moov_dref_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  dref(input).map(|_| MP4BoxType::Dref)
-}
-
-tag!(cmov    "cmov");
-fn moov_cmov_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  cmov(input).map(|_| MP4BoxType::Cmov)
-}
-
-tag!(rmra    "rmra");
-fn moov_rmra_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  rmra(input).map(|_| MP4BoxType::Rmra)
-}
-
-tag!(iods    "iods");
-fn moov_iods_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  iods(input).map(|_| MP4BoxType::Iods)
-}
-
-fn mvhd_box(input:&[u8]) -> IResult<&[u8],MvhdBox> {
-  if input.len() < 100 {
-    Incomplete(Needed::Size(100))
-  } else if input.len() == 100 {
-    mvhd32(input)
-  } else if input.len() == 112 {
-    mvhd64(input)
-  } else {
-    Error(0)
-  }
-}
-
-tag!(mvhd    "mvhd");
-fn moov_mvhd_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  mvhd(input).map(|_| MP4BoxType::Mvhd)
-}
-
-tag!(clip    "clip");
-fn moov_clip_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  clip(input).map(|_| MP4BoxType::Clip)
-}
-
-tag!(trak    "trak");
-fn moov_trak_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  trak(input).map(|_| MP4BoxType::Trak)
-}
-
-tag!(udta   "udta");
-fn moov_udta_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  udta(input).map(|_| MP4BoxType::Udta)
-}
-
-fn moov_box_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  moov_tag(input).map(|_| MP4BoxType::Moov)
-}
-
-tag!(mdat    "mdat");
-fn mdat_box_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  mdat(input).map(|_| MP4BoxType::Mdat)
-}
-tag!(free    "free");
-fn free_box_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  free(input).map(|_| MP4BoxType::Free)
-}
-
-tag!(skip    "skip");
-fn skip_box_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  skip(input).map(|_| MP4BoxType::Skip)
-}
-
-tag!(wide    "wide");
-fn wide_box_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  wide(input).map(|_| MP4BoxType::Wide)
-}
-
-fn unknown_box_type(input:&[u8]) -> IResult<&[u8], MP4BoxType> {
-  Done(input, MP4BoxType::Unknown)
-}
+tag!(mdra     "mdra");
+tag!(dref     "dref");
+tag!(cmov     "cmov");
+tag!(rmra     "rmra");
+tag!(iods     "iods");
+tag!(mvhd     "mvhd");
+tag!(clip     "clip");
+tag!(trak     "trak");
+tag!(udta     "udta");
+tag!(mdat     "mdat");
+tag!(free     "free");
+tag!(skip     "skip");
+tag!(wide     "wide");
 
 enum MP4State {
   Main,
@@ -361,11 +200,27 @@ impl MP4Consumer {
   }
 }
 
-alt!(box_type<&[u8], MP4BoxType>, filetype_box_type | moov_box_type | mdat_box_type | free_box_type | skip_box_type
-     | wide_box_type | unknown_box_type);
+alt!(box_type<&[u8], MP4BoxType>,
+  value!(ftyp,     MP4BoxType::Ftyp) |
+  value!(moov_tag, MP4BoxType::Moov) |
+  value!(mdat,     MP4BoxType::Mdat) |
+  value!(free,     MP4BoxType::Free) |
+  value!(skip,     MP4BoxType::Skip) |
+  value!(wide,     MP4BoxType::Wide) |
+  value!(MP4BoxType::Unknown)
+);
 
-alt!(moov_type<&[u8], MP4BoxType>, moov_mdra_type | moov_dref_type | moov_cmov_type | moov_rmra_type | moov_iods_type |
-     moov_mvhd_type | moov_clip_type | moov_trak_type | moov_udta_type);
+alt!(moov_type<&[u8], MP4BoxType>,
+  value!(mdra, MP4BoxType::Mdra) |
+  value!(dref, MP4BoxType::Dref) |
+  value!(cmov, MP4BoxType::Cmov) |
+  value!(rmra, MP4BoxType::Rmra) |
+  value!(iods, MP4BoxType::Iods) |
+  value!(mvhd, MP4BoxType::Mvhd) |
+  value!(clip, MP4BoxType::Clip) |
+  value!(trak, MP4BoxType::Trak) |
+  value!(udta, MP4BoxType::Udta)
+);
 
 chain!(box_header<&[u8],MP4BoxHeader>,
     length: be_u32 ~
@@ -419,7 +274,7 @@ impl Consumer for MP4Consumer {
                 println!("-> UNKNOWN");
                 println!("bytes:\n{}", (input).to_hex(8));
               },
-              _                   => { println!("invalid"); return ConsumerState::ConsumerError(0)}
+              _                   => { println!("invalid"); return ConsumerState::ConsumerError(Err::Code(0))}
             }
             // current producer position is at input.len()
             // I consumed input.len() - i.len() = 8
@@ -466,7 +321,7 @@ impl Consumer for MP4Consumer {
               MP4BoxType::Trak    => println!("-> TRAK"),
               MP4BoxType::Udta    => println!("-> UDTA"),
               MP4BoxType::Unknown => println!("-> MOOV UNKNOWN"),
-              _                   => { println!("invalid header here: {:?}", header.tag); return ConsumerState::ConsumerError(0)}
+              _                   => { println!("invalid header here: {:?}", header.tag); return ConsumerState::ConsumerError(Err::Code(0))}
             }
             // TODO: check for overflow here
             self.moov_bytes = self.moov_bytes - header.length as usize;
@@ -517,7 +372,7 @@ fn explore_mp4_file(filename: &str) {
     println!("file producer created for {}", filename);
     let mut p = producer;
     let mut c = MP4Consumer{state: MP4State::Main, moov_bytes: 0};
-    c.run(&mut p);
+    c.run(&mut p).unwrap();
 
     //assert!(false);
   });
@@ -1,7 +1,7 @@
 #[macro_use]
 extern crate nom;
 
-use nom::{Consumer,ConsumerState,MemProducer,IResult,Needed};
+use nom::{Consumer,ConsumerState,MemProducer,Producer,ProducerState,IResult,Needed,Err};
 use nom::IResult::*;
 
 #[derive(PartialEq,Eq,Debug)]
@@ -20,6 +20,10 @@ struct TestConsumer {
 tag!(om_parser                     "om");
 tag!(nom_parser                    "nom");
 many1!(nomnom_parser<&[u8],&[u8]>  nom_parser);
+// the consumer gets windows of exactly the size it awaits,
+// so the noms are parsed as complete data
+complete!(complete_nom<&[u8],&[u8]>, nom_parser);
+many1!(window_parser<&[u8],&[u8]>  complete_nom);
 tag!(end_parser                    "kthxbye");
 
 impl Consumer for TestConsumer {
@@ -36,7 +40,7 @@ impl Consumer for TestConsumer {
         }
       },
       State::Middle    => {
-        match nomnom_parser(input) {
+        match window_parser(input) {
           Error(a)         => {
             self.state = State::End;
             ConsumerState::Await(0, 7)
@@ -60,7 +64,7 @@ impl Consumer for TestConsumer {
       },
       State::Done      => {
         // this should not be called
-        ConsumerState::ConsumerError(42)
+        ConsumerState::ConsumerError(Err::Code(42))
       }
     }
   }
@@ -74,7 +78,7 @@ impl Consumer for TestConsumer {
 fn nom1() {
   let mut p = MemProducer::new(b"omnomkthxbye", 4);
   let mut c = TestConsumer{state: State::Beginning, counter: 0};
-  c.run(&mut p);
+  assert!(c.run(&mut p).is_ok());
 
   assert_eq!(c.counter, 1);
   assert_eq!(c.state, State::Done);
@@ -84,7 +88,7 @@ fn nom1() {
 fn nomnomnom() {
   let mut p = MemProducer::new(b"omnomnomnomkthxbye", 4);
   let mut c = TestConsumer{state: State::Beginning, counter: 0};
-  c.run(&mut p);
+  assert!(c.run(&mut p).is_ok());
 
   assert_eq!(c.counter, 3);
   assert_eq!(c.state, State::Done);
@@ -94,7 +98,7 @@ fn nomnomnom() {
 fn no_nomnom() {
   let mut p = MemProducer::new(b"omkthxbye", 4);
   let mut c = TestConsumer{state: State::Beginning, counter: 0};
-  c.run(&mut p);
+  assert!(c.run(&mut p).is_ok());
 
   assert_eq!(c.counter, 0);
   assert_eq!(c.state, State::Done);
@@ -104,9 +108,38 @@ fn no_nomnom() {
 fn impolite() {
   let mut p = MemProducer::new(b"omnomnomnom", 4);
   let mut c = TestConsumer{state: State::Beginning, counter: 0};
-  c.run(&mut p);
+  assert!(c.run(&mut p).is_ok());
 
   assert_eq!(c.counter, 3);
   assert_eq!(c.state, State::Middle);
 }
 
+
+tuple!(omnom_parser<&[u8], (&[u8], Vec<&[u8]>, &[u8])>, om_parser, nomnom_parser, end_parser);
+
+// accumulates the producer's chunks until the parser stops asking for more data
+fn parse_in_chunks(data: &[u8], chunk_size: usize) -> Option<usize> {
+  let mut p = MemProducer::new(data, chunk_size);
+  let mut acc: Vec<u8> = Vec::new();
+  loop {
+    match p.produce() {
+      ProducerState::Data(v) => acc.push_all(v),
+      ProducerState::Eof(v)  => acc.push_all(v),
+      _                      => return None
+    }
+
+    match omnom_parser(&acc[..]) {
+      Done(_, (_, noms, _)) => return Some(noms.len()),
+      Incomplete(_)         => continue,
+      Error(_)              => return None
+    }
+  }
+}
+
+#[test]
+fn chunk_sizes() {
+  let data = b"omnomnomnomkthxbye";
+  for chunk_size in 1..(data.len() + 1) {
+    assert_eq!(parse_in_chunks(data, chunk_size), Some(3));
+  }
+}
@@ -1,7 +1,7 @@
 #[macro_use]
 extern crate nom;
 
-use nom::{IResult,Needed,Producer,FileProducer,ProducerState,FlatMapOpt,Functor,not_line_ending};
+use nom::{IResult,Needed,Producer,FileProducer,ProducerState,not_line_ending};
 use nom::IResult::*;
 
 use std::str;
@@ -26,7 +26,7 @@ fn tag() {
       Done(b"", ())
     }
     pusher!(ps, pr);
-    ps(&mut p);
+    assert!(ps(&mut p).is_ok());
     //assert!(false);
   });
 }